@@ -0,0 +1,513 @@
+//! Async facades over the controller channels of `ChatClient` and `WebBrowser`.
+//!
+//! The clients keep running on their `Processor` loop; a facade owns the
+//! controller side of their channels, turns commands into `async fn` calls and
+//! matches the events coming back to the calls waiting for them. Events nobody
+//! waits for are forwarded to [`AsyncChatClient::events`] / [`AsyncWebBrowser::events`].
+//!
+//! A facade holds no thread of its own: it drains the crossbeam event channel
+//! from a tokio task that sleeps on a timer while the channel is empty, and
+//! stops once the facade is dropped or the client goes away.
+
+use crate::chat_message::MessageStatus;
+use crate::errors::ClientError;
+use crate::media_fetch::placeholder;
+use crate::types::{ChatClientEvent, CorrelatedCommand, CorrelatedEvent, WebBrowserEvent};
+use common::types::{
+    ChatCommand, ChatEvent, Command, Event, File, Message, WebCommand, WebEvent, WebRequest,
+};
+use crossbeam_channel::{Receiver, Sender, TryRecvError};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::MissedTickBehavior;
+use uuid::Uuid;
+use wg_internal::network::NodeId;
+
+type Waiter<T> = oneshot::Sender<Result<T, ClientError>>;
+
+/// How long the pump sleeps once the event channel is empty.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Matches events to the calls waiting for them.
+trait Dispatch: Default + Send + 'static {
+    /// Resolves the calls waiting for `event`, returning whether there were any;
+    /// `correlation` is the id of the `CorrelatedCommand` it results from.
+    fn dispatch(&mut self, correlation: Option<u64>, event: &dyn Event) -> bool;
+    /// Fails every pending call once the client stopped sending events.
+    fn close(&mut self);
+}
+
+/// Calls waiting for events, no longer accepted once the client is gone.
+#[derive(Default)]
+struct Pending<D> {
+    waiters: D,
+    closed: bool,
+}
+
+type SharedPending<D> = Arc<Mutex<Pending<D>>>;
+
+// pumps events from a tokio task; the client channels are crossbeam ones, so
+// the task polls them on a timer instead of blocking a thread on `recv`
+fn spawn_pump<D: Dispatch>(
+    events: Receiver<Box<dyn Event>>,
+) -> (SharedPending<D>, mpsc::UnboundedReceiver<Box<dyn Event>>) {
+    let pending = Arc::new(Mutex::new(Pending::<D>::default()));
+    let (unmatched_send, unmatched_recv) = mpsc::unbounded_channel();
+    let pump_pending = Arc::clone(&pending);
+    tokio::spawn(async move {
+        let mut poll = tokio::time::interval(POLL_INTERVAL);
+        poll.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            let event = match events.try_recv() {
+                Ok(event) => event,
+                // nobody is left to answer once the facade is dropped
+                Err(TryRecvError::Empty) if unmatched_send.is_closed() => return,
+                Err(TryRecvError::Empty) => {
+                    poll.tick().await;
+                    continue;
+                }
+                Err(TryRecvError::Disconnected) => break,
+            };
+            let (correlation, inner) = match event.as_any().downcast_ref::<CorrelatedEvent>() {
+                Some(correlated) => (Some(correlated.correlation_id), correlated.event.as_ref()),
+                None => (None, event.as_ref()),
+            };
+            let consumed = match pump_pending.lock() {
+                Ok(mut pending) => pending.waiters.dispatch(correlation, inner),
+                Err(_) => return,
+            };
+            if !consumed {
+                let _ = unmatched_send.send(event);
+            }
+        }
+        if let Ok(mut pending) = pump_pending.lock() {
+            pending.closed = true;
+            pending.waiters.close();
+        }
+    });
+    (pending, unmatched_recv)
+}
+
+// a call made after the pump stopped would never be answered, so it fails at once
+fn register<D, T>(
+    pending: &Mutex<Pending<D>>,
+    add: impl FnOnce(&mut D, Waiter<T>),
+) -> Result<oneshot::Receiver<Result<T, ClientError>>, ClientError> {
+    let mut pending = pending.lock().map_err(|_| ClientError::Disconnected)?;
+    if pending.closed {
+        return Err(ClientError::Disconnected);
+    }
+    let (send, recv) = oneshot::channel();
+    add(&mut pending.waiters, send);
+    Ok(recv)
+}
+
+async fn wait<T>(recv: oneshot::Receiver<Result<T, ClientError>>) -> Result<T, ClientError> {
+    recv.await.map_err(|_| ClientError::Disconnected)?
+}
+
+fn resolve<T: Clone>(waiters: Vec<Waiter<T>>, value: &T) {
+    for w in waiters {
+        let _ = w.send(Ok(value.clone()));
+    }
+}
+
+// whether every media referenced by `file` arrived, rather than a placeholder
+fn is_complete(file: &File) -> bool {
+    file.text_file.get_media_ids().into_iter().all(|id| {
+        file.media_files
+            .iter()
+            .any(|m| m.id == id && *m != placeholder(id))
+    })
+}
+
+#[derive(Default)]
+struct WebWaiters {
+    files: HashMap<Uuid, Vec<Waiter<File>>>,
+    // last copy sent of a file still missing media, by the id of the file
+    partial: HashMap<Uuid, File>,
+    cached_files: Vec<Waiter<Vec<File>>>,
+}
+
+impl WebWaiters {
+    // a file resolves once complete, or with its last copy once its download
+    // ended with media missing
+    fn file(&mut self, file: &File) -> bool {
+        let uuid = file.text_file.id;
+        if !self.files.contains_key(&uuid) {
+            return false;
+        }
+        if is_complete(file) {
+            self.partial.remove(&uuid);
+            if let Some(waiters) = self.files.remove(&uuid) {
+                resolve(waiters, file);
+            }
+        } else {
+            self.partial.insert(uuid, file.clone());
+        }
+        true
+    }
+
+    fn file_completed(&mut self, uuid: Uuid) -> bool {
+        let Some(file) = self.partial.remove(&uuid) else {
+            return false;
+        };
+        if let Some(waiters) = self.files.remove(&uuid) {
+            resolve(waiters, &file);
+        }
+        true
+    }
+
+    fn fail_file(&mut self, uuid: Uuid, error: impl Fn() -> ClientError) -> bool {
+        self.partial.remove(&uuid);
+        let Some(waiters) = self.files.remove(&uuid) else {
+            return false;
+        };
+        for w in waiters {
+            let _ = w.send(Err(error()));
+        }
+        true
+    }
+}
+
+impl Dispatch for WebWaiters {
+    fn dispatch(&mut self, _correlation: Option<u64>, event: &dyn Event) -> bool {
+        if let Some(event) = event.as_any().downcast_ref::<WebEvent>() {
+            match event {
+                WebEvent::File { file, .. } => self.file(file),
+                WebEvent::CachedFiles { files, .. } if !self.cached_files.is_empty() => {
+                    resolve(std::mem::take(&mut self.cached_files), files);
+                    true
+                }
+                WebEvent::FileNotFound { uuid, .. } => {
+                    self.fail_file(*uuid, || ClientError::FileNotFound(*uuid))
+                }
+                _ => false,
+            }
+        } else if let Some(event) = event.as_any().downcast_ref::<WebBrowserEvent>() {
+            match event {
+                WebBrowserEvent::FileCompleted { file_id, .. } => self.file_completed(*file_id),
+                WebBrowserEvent::RequestTimedOut {
+                    request: WebRequest::FileQuery { file_id },
+                    ..
+                } => Uuid::parse_str(file_id)
+                    .is_ok_and(|uuid| self.fail_file(uuid, || ClientError::TimeoutError)),
+                _ => false,
+            }
+        } else {
+            false
+        }
+    }
+
+    fn close(&mut self) {
+        self.files.clear();
+        self.partial.clear();
+        self.cached_files.clear();
+    }
+}
+
+/// Async API over a running `WebBrowser`.
+pub struct AsyncWebBrowser {
+    commands: Sender<Box<dyn Command>>,
+    waiters: SharedPending<WebWaiters>,
+    events: mpsc::UnboundedReceiver<Box<dyn Event>>,
+}
+
+impl AsyncWebBrowser {
+    /// Takes the controller ends of the browser channels; must be called from
+    /// within a tokio runtime.
+    #[must_use]
+    pub fn new(commands: Sender<Box<dyn Command>>, events: Receiver<Box<dyn Event>>) -> Self {
+        let (waiters, events) = spawn_pump(events);
+        Self {
+            commands,
+            waiters,
+            events,
+        }
+    }
+
+    fn send(&self, cmd: impl Command + 'static) -> Result<(), ClientError> {
+        self.commands
+            .send(Box::new(cmd))
+            .map_err(|_| ClientError::Disconnected)
+    }
+
+    /// Fetches a file with all its media, from the cache or the network; a
+    /// file whose media could not all be fetched comes with placeholders.
+    pub async fn get_file(&self, uuid: Uuid) -> Result<File, ClientError> {
+        let recv = register(&self.waiters, |w, s| {
+            w.files.entry(uuid).or_default().push(s);
+        })?;
+        self.send(WebCommand::GetFile(uuid))?;
+        wait(recv).await
+    }
+
+    pub async fn get_cached_files(&self) -> Result<Vec<File>, ClientError> {
+        let recv = register(&self.waiters, |w, s| w.cached_files.push(s))?;
+        self.send(WebCommand::GetCachedFiles)?;
+        wait(recv).await
+    }
+
+    /// Events not answering any pending call.
+    pub fn events(&mut self) -> &mut mpsc::UnboundedReceiver<Box<dyn Event>> {
+        &mut self.events
+    }
+}
+
+#[derive(Default)]
+struct ChatWaiters {
+    // sends by the correlation id of their command
+    messages: HashMap<u64, Waiter<MessageStatus>>,
+    clients: Vec<Waiter<Vec<NodeId>>>,
+    history: Vec<Waiter<HashMap<NodeId, Vec<Message>>>>,
+}
+
+impl ChatWaiters {
+    // a send resolves once its message is acknowledged or failed
+    fn status_changed(&mut self, correlation: u64, status: MessageStatus) -> bool {
+        let result = match status {
+            MessageStatus::Delivered | MessageStatus::Read => Ok(status),
+            MessageStatus::Failed => Err(ClientError::DeliveryFailed),
+            MessageStatus::Queued | MessageStatus::Sent => return false,
+        };
+        match self.messages.remove(&correlation) {
+            Some(w) => {
+                let _ = w.send(result);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Dispatch for ChatWaiters {
+    fn dispatch(&mut self, correlation: Option<u64>, event: &dyn Event) -> bool {
+        if let Some(event) = event.as_any().downcast_ref::<ChatEvent>() {
+            match event {
+                ChatEvent::RegisteredClients { list, .. } if !self.clients.is_empty() => {
+                    resolve(std::mem::take(&mut self.clients), list);
+                    true
+                }
+                ChatEvent::ChatHistory { history, .. } if !self.history.is_empty() => {
+                    resolve(std::mem::take(&mut self.history), history);
+                    true
+                }
+                _ => false,
+            }
+        } else if let Some(ChatClientEvent::MessageStatusChanged { status, .. }) =
+            event.as_any().downcast_ref::<ChatClientEvent>()
+            && let Some(correlation) = correlation
+        {
+            self.status_changed(correlation, *status)
+        } else {
+            false
+        }
+    }
+
+    fn close(&mut self) {
+        self.messages.clear();
+        self.clients.clear();
+        self.history.clear();
+    }
+}
+
+/// Async API over a running `ChatClient`.
+pub struct AsyncChatClient {
+    commands: Sender<Box<dyn Command>>,
+    waiters: SharedPending<ChatWaiters>,
+    events: mpsc::UnboundedReceiver<Box<dyn Event>>,
+    next_correlation: AtomicU64,
+}
+
+impl AsyncChatClient {
+    /// Takes the controller ends of the client channels; must be called from
+    /// within a tokio runtime.
+    #[must_use]
+    pub fn new(commands: Sender<Box<dyn Command>>, events: Receiver<Box<dyn Event>>) -> Self {
+        let (waiters, events) = spawn_pump(events);
+        Self {
+            commands,
+            waiters,
+            events,
+            next_correlation: AtomicU64::new(0),
+        }
+    }
+
+    fn send(&self, cmd: impl Command + 'static) -> Result<(), ClientError> {
+        self.commands
+            .send(Box::new(cmd))
+            .map_err(|_| ClientError::Disconnected)
+    }
+
+    /// Sends a message, resolving once the recipient acknowledged it.
+    pub async fn send_message(&self, message: Message) -> Result<MessageStatus, ClientError> {
+        let correlation_id = self.next_correlation.fetch_add(1, Ordering::Relaxed);
+        let recv = register(&self.waiters, |w, s| {
+            w.messages.insert(correlation_id, s);
+        })?;
+        self.send(CorrelatedCommand {
+            correlation_id,
+            command: Box::new(ChatCommand::SendMessage(message)),
+        })?;
+        wait(recv).await
+    }
+
+    pub async fn registered_clients(&self) -> Result<Vec<NodeId>, ClientError> {
+        let recv = register(&self.waiters, |w, s| w.clients.push(s))?;
+        self.send(ChatCommand::GetRegisteredClients)?;
+        wait(recv).await
+    }
+
+    pub async fn chats_history(&self) -> Result<HashMap<NodeId, Vec<Message>>, ClientError> {
+        let recv = register(&self.waiters, |w, s| w.history.push(s))?;
+        self.send(ChatCommand::GetChatsHistory)?;
+        wait(recv).await
+    }
+
+    /// Events not answering any pending call.
+    pub fn events(&mut self) -> &mut mpsc::UnboundedReceiver<Box<dyn Event>> {
+        &mut self.events
+    }
+}
+
+#[cfg(test)]
+mod async_client_tests {
+    use super::*;
+    use common::types::{MediaFile, MediaReference, TextFile};
+    use crossbeam::channel::unbounded;
+
+    #[tokio::test]
+    /// Tests that `get_file` resolves with the matching `File` event
+    async fn test_get_file() {
+        let (command_send, command_recv) = unbounded::<Box<dyn Command>>();
+        let (event_send, event_recv) = unbounded::<Box<dyn Event>>();
+        let text_file = TextFile::new("Async".to_string(), "Content".to_string(), vec![]);
+        let uuid = text_file.id;
+
+        // stands in for the browser answering from its cache
+        std::thread::spawn(move || {
+            let _ = command_recv.recv();
+            let other = TextFile::new("Other".to_string(), "Content".to_string(), vec![]);
+            for file in [other, text_file] {
+                let _ = event_send.send(Box::new(WebEvent::File {
+                    notification_from: 1,
+                    file: File::new(file, vec![]),
+                }));
+            }
+        });
+
+        let mut browser = AsyncWebBrowser::new(command_send, event_recv);
+        let file = browser.get_file(uuid).await.unwrap();
+        assert_eq!(file.text_file.id, uuid);
+        assert!(browser.events().recv().await.is_some());
+    }
+
+    #[tokio::test]
+    /// Tests that `get_file` skips a copy sent with placeholders and resolves
+    /// with the file sent once its download completed
+    async fn test_get_file_partial_delivery() {
+        let (command_send, command_recv) = unbounded::<Box<dyn Command>>();
+        let (event_send, event_recv) = unbounded::<Box<dyn Event>>();
+        let media_ref = MediaReference::new(6);
+        let media = MediaFile {
+            id: media_ref.id,
+            title: "Image".to_string(),
+            content: vec![vec![1]],
+        };
+        let text_file = TextFile::new("Async".to_string(), "Content".to_string(), vec![media_ref]);
+        let uuid = text_file.id;
+
+        // stands in for the browser delivering a partial file before the complete one
+        std::thread::spawn(move || {
+            let _ = command_recv.recv();
+            let events: [Box<dyn Event>; 3] = [
+                Box::new(WebEvent::File {
+                    notification_from: 1,
+                    file: File::new(text_file.clone(), vec![placeholder(media.id)]),
+                }),
+                Box::new(WebEvent::File {
+                    notification_from: 1,
+                    file: File::new(text_file, vec![media]),
+                }),
+                Box::new(WebBrowserEvent::FileCompleted {
+                    notification_from: 1,
+                    file_id: uuid,
+                    missing: vec![],
+                }),
+            ];
+            for event in events {
+                let _ = event_send.send(event);
+            }
+        });
+
+        let browser = AsyncWebBrowser::new(command_send, event_recv);
+        let file = browser.get_file(uuid).await.unwrap();
+        assert!(is_complete(&file));
+        assert_eq!(file.media_files[0].content, vec![vec![1]]);
+    }
+
+    #[tokio::test]
+    /// Tests that `send_message` resolves once the message is delivered
+    async fn test_send_message_delivery() {
+        let (command_send, command_recv) = unbounded::<Box<dyn Command>>();
+        let (event_send, event_recv) = unbounded::<Box<dyn Event>>();
+
+        // stands in for the client, answering under the correlation id of the send
+        std::thread::spawn(move || {
+            let Ok(cmd) = command_recv.recv() else {
+                return;
+            };
+            let Some(correlated) = cmd.as_any().downcast_ref::<CorrelatedCommand>() else {
+                return;
+            };
+            let id = Uuid::from_u128(1);
+            for (correlation_id, status) in [
+                (correlated.correlation_id + 1, MessageStatus::Delivered),
+                (correlated.correlation_id, MessageStatus::Sent),
+                (correlated.correlation_id, MessageStatus::Delivered),
+            ] {
+                let _ = event_send.send(Box::new(CorrelatedEvent {
+                    correlation_id,
+                    event: Box::new(ChatClientEvent::MessageStatusChanged {
+                        notification_from: 1,
+                        peer: 10,
+                        id,
+                        status,
+                    }),
+                }));
+            }
+        });
+
+        let client = AsyncChatClient::new(command_send, event_recv);
+        let status = client
+            .send_message(Message::new(1, 10, "Async".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(status, MessageStatus::Delivered);
+    }
+
+    #[tokio::test]
+    /// Tests that pending calls fail once the client goes away
+    async fn test_disconnected() {
+        let (command_send, _command_recv) = unbounded::<Box<dyn Command>>();
+        let (event_send, event_recv) = unbounded::<Box<dyn Event>>();
+
+        let client = AsyncChatClient::new(command_send, event_recv);
+        drop(event_send);
+        assert!(matches!(
+            client.registered_clients().await,
+            Err(ClientError::Disconnected)
+        ));
+        // calls made after the pump stopped fail too, instead of waiting forever
+        while !client.waiters.lock().unwrap().closed {
+            tokio::task::yield_now().await;
+        }
+        assert!(matches!(
+            client.chats_history().await,
+            Err(ClientError::Disconnected)
+        ));
+    }
+}
@@ -0,0 +1,132 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub id: Uuid,
+    pub title: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Stored {
+    bookmarks: Vec<Bookmark>,
+    /// Text files kept in the cache regardless of eviction.
+    pinned: Vec<Uuid>,
+}
+
+/// Bookmarked and pinned files, saved to a JSON file when opened from one.
+#[derive(Debug, Default)]
+pub struct Bookmarks {
+    path: Option<PathBuf>,
+    stored: Stored,
+}
+
+impl Bookmarks {
+    /// Loads the bookmarks saved at `path`, starting empty when there are none.
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let stored = match fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data).map_err(io::Error::other)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Stored::default(),
+            Err(e) => return Err(e),
+        };
+        Ok(Self {
+            path: Some(path),
+            stored,
+        })
+    }
+
+    // replaces the saved file atomically, like `DiskCache::store`
+    fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let data = serde_json::to_vec(&self.stored).map_err(io::Error::other)?;
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, data)?;
+        fs::rename(tmp, path)
+    }
+
+    /// Bookmarks a file, replacing the title of an existing bookmark.
+    pub fn add(&mut self, bookmark: Bookmark) -> io::Result<()> {
+        match self
+            .stored
+            .bookmarks
+            .iter_mut()
+            .find(|b| b.id == bookmark.id)
+        {
+            Some(existing) => *existing = bookmark,
+            None => self.stored.bookmarks.push(bookmark),
+        }
+        self.save()
+    }
+
+    pub fn remove(&mut self, id: Uuid) -> io::Result<()> {
+        self.stored.bookmarks.retain(|b| b.id != id);
+        self.save()
+    }
+
+    #[must_use]
+    pub fn list(&self) -> &[Bookmark] {
+        &self.stored.bookmarks
+    }
+
+    pub fn pin(&mut self, id: Uuid) -> io::Result<()> {
+        if !self.stored.pinned.contains(&id) {
+            self.stored.pinned.push(id);
+        }
+        self.save()
+    }
+
+    pub fn unpin(&mut self, id: Uuid) -> io::Result<()> {
+        self.stored.pinned.retain(|p| *p != id);
+        self.save()
+    }
+
+    #[must_use]
+    pub fn pinned(&self) -> &[Uuid] {
+        &self.stored.pinned
+    }
+}
+
+#[cfg(test)]
+mod bookmarks_tests {
+    use super::*;
+
+    #[test]
+    /// Tests that bookmarks and pins are reloaded from their file
+    fn test_reload() {
+        let path = std::env::temp_dir().join("client-bookmarks-reload.json");
+        let _ = fs::remove_file(&path);
+        let id = Uuid::from_u128(1);
+
+        let mut bookmarks = Bookmarks::open(&path).unwrap();
+        bookmarks
+            .add(Bookmark {
+                id,
+                title: "Old".to_string(),
+            })
+            .unwrap();
+        bookmarks
+            .add(Bookmark {
+                id,
+                title: "Article".to_string(),
+            })
+            .unwrap();
+        bookmarks.pin(id).unwrap();
+
+        let reloaded = Bookmarks::open(&path).unwrap();
+        assert_eq!(
+            reloaded.list(),
+            &[Bookmark {
+                id,
+                title: "Article".to_string()
+            }]
+        );
+        assert_eq!(reloaded.pinned(), &[id]);
+        let _ = fs::remove_file(path);
+    }
+}
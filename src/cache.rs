@@ -0,0 +1,460 @@
+use common::types::{File, MediaFile, TextFile};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+/// Which entry is dropped first when the cache is over budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// Least recently used.
+    Lru,
+    /// Least frequently used, ties broken by recency.
+    Lfu,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CacheConfig {
+    pub max_bytes: usize,
+    pub max_entries: usize,
+    pub policy: EvictionPolicy,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            max_bytes: 64 * 1024 * 1024,
+            max_entries: 256,
+            policy: EvictionPolicy::Lru,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub entries: usize,
+    pub bytes: usize,
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    /// Ids of the media received so far, in arrival order.
+    media: Vec<Uuid>,
+    bytes: usize,
+    last_used: u64,
+    uses: u64,
+}
+
+#[derive(Debug)]
+struct StoredMedia {
+    file: MediaFile,
+    /// Cached text files holding the media.
+    refs: usize,
+}
+
+/// Text files and their media, bounded by entry count and byte budget.
+///
+/// Media are stored once by id whatever the number of text files referencing
+/// them, and dropped with the last of these.
+#[derive(Debug)]
+pub struct FileCache {
+    config: CacheConfig,
+    entries: HashMap<TextFile, CacheEntry>,
+    media: HashMap<Uuid, StoredMedia>,
+    pinned: HashSet<Uuid>, // text file ids never evicted nor flushed
+    clock: u64,
+    bytes: usize,
+    stats: CacheStats,
+}
+
+fn text_file_size(file: &TextFile) -> usize {
+    serde_json::to_vec(file).map_or(0, |v| v.len())
+}
+
+fn media_file_size(media: &MediaFile) -> usize {
+    media.title.len() + media.content.iter().map(Vec::len).sum::<usize>()
+}
+
+impl FileCache {
+    #[must_use]
+    pub fn new(config: CacheConfig) -> Self {
+        Self {
+            config,
+            entries: HashMap::new(),
+            media: HashMap::new(),
+            pinned: HashSet::new(),
+            clock: 0,
+            bytes: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn set_config(&mut self, config: CacheConfig) {
+        self.config = config;
+        self.evict(None);
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    // takes a reference to `media`, storing it if it is new
+    fn retain_media(&mut self, media: MediaFile) -> Uuid {
+        let id = media.id;
+        if let Some(stored) = self.media.get_mut(&id) {
+            stored.refs += 1;
+        } else {
+            self.bytes += media_file_size(&media);
+            self.media.insert(
+                id,
+                StoredMedia {
+                    file: media,
+                    refs: 1,
+                },
+            );
+        }
+        id
+    }
+
+    // drops the references of a removed entry, and the media left unreferenced
+    fn release_media(&mut self, entry: &CacheEntry) {
+        self.bytes -= entry.bytes;
+        for id in &entry.media {
+            if let Some(stored) = self.media.get_mut(id) {
+                stored.refs -= 1;
+                if stored.refs == 0 {
+                    self.bytes -= media_file_size(&stored.file);
+                    self.media.remove(id);
+                }
+            }
+        }
+    }
+
+    fn assemble(&self, entry: &CacheEntry) -> Vec<MediaFile> {
+        entry
+            .media
+            .iter()
+            .filter_map(|id| self.media.get(id).map(|m| m.file.clone()))
+            .collect()
+    }
+
+    /// Caches `file` with `media`, returning the text files evicted to make room.
+    pub fn insert(&mut self, file: TextFile, media: Vec<MediaFile>) -> Vec<TextFile> {
+        let bytes = text_file_size(&file);
+        let last_used = self.tick();
+        if let Some(old) = self.entries.remove(&file) {
+            self.release_media(&old);
+        }
+        self.bytes += bytes;
+        let mut ids = vec![];
+        for m in media {
+            if !ids.contains(&m.id) {
+                ids.push(self.retain_media(m));
+            }
+        }
+        self.entries.insert(
+            file.clone(),
+            CacheEntry {
+                media: ids,
+                bytes,
+                last_used,
+                uses: 1,
+            },
+        );
+        self.evict(Some(&file))
+    }
+
+    /// Attaches `media` to a cached text file, returning the text files evicted
+    /// to make room; a media the file already holds is left as is.
+    pub fn push_media(&mut self, file: &TextFile, media: MediaFile) -> Option<Vec<TextFile>> {
+        let last_used = self.tick();
+        let entry = self.entries.get(file)?;
+        if entry.media.contains(&media.id) {
+            return Some(vec![]);
+        }
+        let id = self.retain_media(media);
+        let entry = self.entries.get_mut(file)?;
+        entry.media.push(id);
+        entry.last_used = last_used;
+        Some(self.evict(Some(file)))
+    }
+
+    /// Looks a file up by id, counting a hit or a miss.
+    pub fn get(&mut self, id: Uuid) -> Option<File> {
+        let last_used = self.tick();
+        let Some((file, entry)) = self.entries.iter_mut().find(|(f, _)| f.id == id) else {
+            self.stats.misses += 1;
+            return None;
+        };
+        self.stats.hits += 1;
+        entry.last_used = last_used;
+        entry.uses += 1;
+        let file = file.clone();
+        let media = self.entries.get(&file).map(|e| self.assemble(e));
+        Some(File::new(file, media.unwrap_or_default()))
+    }
+
+    /// Cached text files, the most used first.
+    #[must_use]
+    pub fn most_used(&self) -> Vec<&TextFile> {
+        let mut files = self.entries.iter().collect::<Vec<_>>();
+        files.sort_by_key(|(_, e)| Reverse((e.uses, e.last_used)));
+        files.into_iter().map(|(f, _)| f).collect()
+    }
+
+    /// Media attached to a cached text file, without counting a hit.
+    pub fn media(&self, file: &TextFile) -> Option<Vec<MediaFile>> {
+        self.entries.get(file).map(|e| self.assemble(e))
+    }
+
+    /// Whether the cached text file `file` holds the media `media_id`.
+    pub fn has_media(&self, file: &TextFile, media_id: Uuid) -> bool {
+        self.entries
+            .get(file)
+            .is_some_and(|e| e.media.contains(&media_id))
+    }
+
+    /// A stored media, whichever text files hold it.
+    pub fn media_by_id(&self, media_id: Uuid) -> Option<&MediaFile> {
+        self.media.get(&media_id).map(|m| &m.file)
+    }
+
+    /// Every stored media, once each.
+    pub fn media_files(&self) -> impl Iterator<Item = &MediaFile> {
+        self.media.values().map(|m| &m.file)
+    }
+
+    /// Every cached file, assembled from the shared media.
+    pub fn files(&self) -> Vec<File> {
+        self.entries
+            .iter()
+            .map(|(f, e)| File::new(f.clone(), self.assemble(e)))
+            .collect()
+    }
+
+    pub fn contains_key(&self, file: &TextFile) -> bool {
+        self.entries.contains_key(file)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &TextFile> {
+        self.entries.keys()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Exempts a text file from eviction and flushing, cached or not yet.
+    pub fn pin(&mut self, id: Uuid) {
+        self.pinned.insert(id);
+    }
+
+    /// Makes a pinned text file evictable again, returning what was evicted as a result.
+    pub fn unpin(&mut self, id: Uuid) -> Vec<TextFile> {
+        self.pinned.remove(&id);
+        self.evict(None)
+    }
+
+    pub fn is_pinned(&self, id: Uuid) -> bool {
+        self.pinned.contains(&id)
+    }
+
+    /// Drops the entry of `file`, returning whether it was cached.
+    pub fn remove(&mut self, file: &TextFile) -> bool {
+        let Some(entry) = self.entries.remove(file) else {
+            return false;
+        };
+        self.release_media(&entry);
+        true
+    }
+
+    /// Drops every entry but the pinned ones.
+    pub fn flush(&mut self) {
+        let dropped = self
+            .entries
+            .keys()
+            .filter(|f| !self.pinned.contains(&f.id))
+            .cloned()
+            .collect::<Vec<_>>();
+        for file in dropped {
+            if let Some(entry) = self.entries.remove(&file) {
+                self.release_media(&entry);
+            }
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            entries: self.entries.len(),
+            bytes: self.bytes,
+            ..self.stats
+        }
+    }
+
+    fn over_budget(&self) -> bool {
+        self.bytes > self.config.max_bytes || self.entries.len() > self.config.max_entries
+    }
+
+    // drop entries until the cache fits its budget, never evicting `keep`
+    fn evict(&mut self, keep: Option<&TextFile>) -> Vec<TextFile> {
+        let mut evicted = vec![];
+        while self.over_budget() {
+            let victim = self
+                .entries
+                .iter()
+                .filter(|(f, _)| Some(*f) != keep && !self.pinned.contains(&f.id))
+                .min_by_key(|(_, e)| match self.config.policy {
+                    EvictionPolicy::Lru => (e.last_used, 0),
+                    EvictionPolicy::Lfu => (e.uses, e.last_used),
+                })
+                .map(|(f, _)| f.clone());
+            let Some(victim) = victim else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&victim) {
+                self.release_media(&entry);
+                self.stats.evictions += 1;
+            }
+            evicted.push(victim);
+        }
+        evicted
+    }
+}
+
+#[cfg(test)]
+mod cache_tests {
+    use super::*;
+
+    fn text_file(title: &str) -> TextFile {
+        TextFile::new(title.to_string(), "Content".to_string(), vec![])
+    }
+
+    #[test]
+    /// Tests LRU eviction when the entry limit is exceeded
+    fn test_lru_eviction() {
+        let mut cache = FileCache::new(CacheConfig {
+            max_entries: 2,
+            ..CacheConfig::default()
+        });
+        let (a, b, c) = (text_file("a"), text_file("b"), text_file("c"));
+        cache.insert(a.clone(), vec![]);
+        cache.insert(b.clone(), vec![]);
+        assert!(cache.get(a.id).is_some());
+
+        assert_eq!(cache.insert(c.clone(), vec![]), vec![b.clone()]);
+        assert!(cache.contains_key(&a));
+        assert!(cache.contains_key(&c));
+        assert_eq!(cache.stats().evictions, 1);
+    }
+
+    #[test]
+    /// Tests LFU eviction prefers the least used entry
+    fn test_lfu_eviction() {
+        let mut cache = FileCache::new(CacheConfig {
+            max_entries: 2,
+            policy: EvictionPolicy::Lfu,
+            ..CacheConfig::default()
+        });
+        let (a, b, c) = (text_file("a"), text_file("b"), text_file("c"));
+        cache.insert(a.clone(), vec![]);
+        cache.insert(b.clone(), vec![]);
+        cache.get(a.id);
+        cache.get(a.id);
+        cache.get(b.id);
+
+        assert_eq!(cache.insert(c, vec![]), vec![b]);
+    }
+
+    #[test]
+    /// Tests that media content counts towards the byte budget
+    fn test_byte_budget() {
+        let a = text_file("a");
+        let b = text_file("b");
+        let budget = text_file_size(&a) + text_file_size(&b) + 8;
+        let mut cache = FileCache::new(CacheConfig {
+            max_bytes: budget,
+            ..CacheConfig::default()
+        });
+        cache.insert(a.clone(), vec![]);
+        cache.insert(b.clone(), vec![]);
+
+        let media = MediaFile {
+            id: Uuid::from_u128(1),
+            title: String::new(),
+            content: vec![vec![0; 16]],
+        };
+        let evicted = cache.push_media(&b, media).unwrap();
+        assert_eq!(evicted, vec![a.clone()]);
+        assert!(!cache.contains_key(&a));
+        assert!(cache.stats().bytes <= budget);
+    }
+
+    #[test]
+    /// Tests that a media shared by several files is stored and counted once,
+    /// until the last of them goes
+    fn test_shared_media() {
+        let mut cache = FileCache::new(CacheConfig::default());
+        let (a, b) = (text_file("a"), text_file("b"));
+        let media = MediaFile {
+            id: Uuid::from_u128(1),
+            title: "Image".to_string(),
+            content: vec![vec![0; 100]],
+        };
+        cache.insert(a.clone(), vec![media.clone()]);
+        let single = cache.stats().bytes;
+        cache.insert(b.clone(), vec![]);
+        cache.push_media(&b, media.clone());
+        assert_eq!(cache.stats().bytes, single + text_file_size(&b));
+        assert_eq!(cache.media_files().count(), 1);
+        assert_eq!(cache.get(b.id).unwrap().media_files, vec![media.clone()]);
+
+        cache.insert(a.clone(), vec![]);
+        assert!(cache.media_by_id(media.id).is_some());
+        cache.insert(b, vec![]);
+        assert!(cache.media_by_id(media.id).is_none());
+    }
+
+    #[test]
+    /// Tests that pinned entries survive eviction and flushing
+    fn test_pinned_entries() {
+        let mut cache = FileCache::new(CacheConfig {
+            max_entries: 1,
+            ..CacheConfig::default()
+        });
+        let (a, b) = (text_file("a"), text_file("b"));
+        cache.pin(a.id);
+        cache.insert(a.clone(), vec![]);
+        // over budget, but the only other entry is pinned
+        assert!(cache.insert(b.clone(), vec![]).is_empty());
+
+        cache.flush();
+        assert!(cache.contains_key(&a));
+        assert_eq!(cache.len(), 1);
+        cache.insert(b.clone(), vec![]);
+        assert_eq!(cache.unpin(a.id), vec![a]);
+    }
+
+    #[test]
+    /// Tests hit/miss counters and flushing
+    fn test_stats_and_flush() {
+        let mut cache = FileCache::new(CacheConfig::default());
+        let a = text_file("a");
+        cache.insert(a.clone(), vec![]);
+
+        assert!(cache.get(a.id).is_some());
+        assert!(cache.get(Uuid::nil()).is_none());
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (1, 1, 1));
+
+        cache.flush();
+        assert!(cache.is_empty());
+        assert_eq!(cache.stats().bytes, 0);
+    }
+}
@@ -0,0 +1,320 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+use wg_internal::network::NodeId;
+
+#[derive(Debug, Clone, Copy)]
+pub struct CatalogConfig {
+    /// How long a file list is used before it is queried again.
+    pub refresh_every: Duration,
+    /// Age after which a list that could not be refreshed is considered stale.
+    pub stale_after: Duration,
+}
+
+impl Default for CatalogConfig {
+    fn default() -> Self {
+        Self {
+            refresh_every: Duration::from_secs(30),
+            stale_after: Duration::from_secs(90),
+        }
+    }
+}
+
+/// Files a server published and withdrew between two versions of its list.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CatalogDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl CatalogDiff {
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// A file listed by a text server, parsed from its `"<uuid>:<title>"` entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CatalogEntry {
+    pub id: Uuid,
+    pub title: String,
+    pub server: NodeId,
+}
+
+impl CatalogEntry {
+    /// Parses a list entry, the title being optional.
+    #[must_use]
+    pub fn parse(raw: &str, server: NodeId) -> Option<Self> {
+        let (id, title) = raw.split_once(':').unwrap_or((raw, ""));
+        Some(Self {
+            id: Uuid::parse_str(id.trim()).ok()?,
+            title: title.trim().to_string(),
+            server,
+        })
+    }
+}
+
+// lower is better: substring matches rank by position, before every in-order
+// (fuzzy) match, which rank by how spread out the matched characters are
+fn match_score(title: &str, query: &str) -> Option<(bool, usize)> {
+    let title = title.to_lowercase();
+    let query = query.to_lowercase();
+    if let Some(pos) = title.find(&query) {
+        return Some((false, pos));
+    }
+    let mut chars = title.char_indices();
+    let mut first = None;
+    let mut last = 0;
+    for q in query.chars() {
+        let (i, _) = chars.find(|(_, c)| *c == q)?;
+        first.get_or_insert(i);
+        last = i;
+    }
+    Some((true, last - first.unwrap_or(0)))
+}
+
+#[derive(Debug, Clone, Default)]
+struct ServerCatalog {
+    /// Raw list as received, entries that do not parse included.
+    files: Vec<String>,
+    entries: Vec<CatalogEntry>,
+    /// When the list was last received, `None` until the first one arrives.
+    updated: Option<Instant>,
+    requested: Option<Instant>,
+    stale: bool,
+}
+
+/// File lists of the known text servers, refreshed periodically.
+#[derive(Debug, Default)]
+pub struct Catalog {
+    config: CatalogConfig,
+    servers: HashMap<NodeId, ServerCatalog>,
+}
+
+impl Catalog {
+    #[must_use]
+    pub fn new(config: CatalogConfig) -> Self {
+        Self {
+            config,
+            servers: HashMap::new(),
+        }
+    }
+
+    pub fn set_config(&mut self, config: CatalogConfig) {
+        self.config = config;
+    }
+
+    /// Records a text server whose list has not been received yet.
+    pub fn discover(&mut self, server: NodeId) {
+        self.servers.entry(server).or_default();
+    }
+
+    /// Forgets a server whose first list never came, returning whether it did.
+    pub fn forget_unlisted(&mut self, server: NodeId) -> bool {
+        !self.is_listed(server) && self.servers.remove(&server).is_some()
+    }
+
+    #[must_use]
+    pub fn contains(&self, server: NodeId) -> bool {
+        self.servers.contains_key(&server)
+    }
+
+    pub fn servers(&self) -> impl Iterator<Item = NodeId> + '_ {
+        self.servers.keys().copied()
+    }
+
+    #[must_use]
+    pub fn files(&self, server: NodeId) -> Option<&Vec<String>> {
+        self.servers.get(&server).map(|s| &s.files)
+    }
+
+    /// Replaces the list of `server`, returning what changed since the previous one.
+    pub fn update(&mut self, server: NodeId, files: Vec<String>, now: Instant) -> CatalogDiff {
+        let entry = self.servers.entry(server).or_default();
+        let diff = CatalogDiff {
+            added: files
+                .iter()
+                .filter(|f| !entry.files.contains(f))
+                .cloned()
+                .collect(),
+            removed: entry
+                .files
+                .iter()
+                .filter(|f| !files.contains(f))
+                .cloned()
+                .collect(),
+        };
+        entry.entries = files
+            .iter()
+            .filter_map(|f| CatalogEntry::parse(f, server))
+            .collect();
+        entry.files = files;
+        entry.updated = Some(now);
+        entry.requested = None;
+        entry.stale = false;
+        diff
+    }
+
+    #[must_use]
+    pub fn is_listed(&self, server: NodeId) -> bool {
+        self.servers
+            .get(&server)
+            .is_some_and(|s| s.updated.is_some())
+    }
+
+    #[must_use]
+    pub fn all_listed(&self) -> bool {
+        self.servers.values().all(|s| s.updated.is_some())
+    }
+
+    /// A server listing `file`, preferring the ones whose list is not stale.
+    #[must_use]
+    pub fn locate(&self, file: Uuid) -> Option<NodeId> {
+        self.servers_listing(file, None)
+    }
+
+    /// Another server listing `file`, used when re-routing away from `current`.
+    #[must_use]
+    pub fn alternative(&self, file: Uuid, current: NodeId) -> Option<NodeId> {
+        self.servers_listing(file, Some(current))
+    }
+
+    fn servers_listing(&self, file: Uuid, except: Option<NodeId>) -> Option<NodeId> {
+        self.servers
+            .iter()
+            .filter(|(id, s)| Some(**id) != except && s.entries.iter().any(|e| e.id == file))
+            .min_by_key(|(id, s)| (s.stale, **id))
+            .map(|(id, _)| *id)
+    }
+
+    #[must_use]
+    pub fn entry(&self, file: Uuid) -> Option<&CatalogEntry> {
+        self.servers
+            .values()
+            .flat_map(|s| &s.entries)
+            .find(|e| e.id == file)
+    }
+
+    /// Entries of every server whose title matches `query`, as a substring or
+    /// with its characters in order, best matches first.
+    #[must_use]
+    pub fn search(&self, query: &str) -> Vec<CatalogEntry> {
+        let mut found = self
+            .servers
+            .values()
+            .flat_map(|s| &s.entries)
+            .filter_map(|e| Some((match_score(&e.title, query)?, e)))
+            .collect::<Vec<_>>();
+        found.sort_by(|(a, x), (b, y)| {
+            a.cmp(b)
+                .then_with(|| x.title.cmp(&y.title))
+                .then(x.server.cmp(&y.server))
+        });
+        found.into_iter().map(|(_, e)| e.clone()).collect()
+    }
+
+    /// Listed servers whose list is due for a refresh and not already being
+    /// queried, marking them as queried.
+    pub fn due_for_refresh(&mut self, now: Instant) -> Vec<NodeId> {
+        let refresh_every = self.config.refresh_every;
+        self.servers
+            .iter_mut()
+            .filter(|(_, s)| {
+                let last = s.requested.or(s.updated);
+                last.is_some_and(|t| now.duration_since(t) >= refresh_every)
+            })
+            .map(|(id, s)| {
+                s.requested = Some(now);
+                *id
+            })
+            .collect()
+    }
+
+    /// Servers whose list just went stale, each reported once until refreshed.
+    pub fn newly_stale(&mut self, now: Instant) -> Vec<NodeId> {
+        let stale_after = self.config.stale_after;
+        self.servers
+            .iter_mut()
+            .filter(|(_, s)| {
+                !s.stale
+                    && s.updated
+                        .is_some_and(|t| now.duration_since(t) >= stale_after)
+            })
+            .map(|(id, s)| {
+                s.stale = true;
+                *id
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod catalog_tests {
+    use super::*;
+
+    #[test]
+    /// Tests that updates replace lists, report diffs and reset staleness
+    fn test_update_and_staleness() {
+        let config = CatalogConfig {
+            refresh_every: Duration::from_secs(10),
+            stale_after: Duration::from_secs(20),
+        };
+        let mut catalog = Catalog::new(config);
+        let start = Instant::now();
+        catalog.discover(5);
+        assert!(!catalog.all_listed());
+
+        catalog.update(5, vec!["a".to_string(), "b".to_string()], start);
+        let diff = catalog.update(5, vec!["b".to_string(), "c".to_string()], start);
+        assert_eq!(diff.added, vec!["c".to_string()]);
+        assert_eq!(diff.removed, vec!["a".to_string()]);
+        assert_eq!(catalog.files(5).unwrap().len(), 2);
+
+        let later = start + Duration::from_secs(10);
+        assert_eq!(catalog.due_for_refresh(later), vec![5]);
+        assert!(catalog.due_for_refresh(later).is_empty());
+
+        let stale = start + Duration::from_secs(20);
+        assert_eq!(catalog.newly_stale(stale), vec![5]);
+        assert!(catalog.newly_stale(stale).is_empty());
+        catalog.update(5, vec![], stale);
+        assert!(catalog.newly_stale(stale).is_empty());
+    }
+
+    #[test]
+    /// Tests that entries are parsed and searched by title
+    fn test_search() {
+        let mut catalog = Catalog::default();
+        let (first, second, third) = (Uuid::from_u128(1), Uuid::from_u128(2), Uuid::from_u128(3));
+        let files = vec![
+            format!("{first}:Rust ownership"),
+            format!("{second}: Routing in drone networks"),
+            format!("{third}:Nest"),
+            "not-a-uuid:Broken".to_string(),
+        ];
+        catalog.update(5, files, Instant::now());
+        catalog.update(
+            6,
+            vec![format!("{second}:Routing in drone networks")],
+            Instant::now(),
+        );
+
+        assert_eq!(catalog.locate(first), Some(5));
+        assert_eq!(catalog.alternative(second, 5), Some(6));
+        let titles = |query| {
+            catalog
+                .search(query)
+                .into_iter()
+                .map(|e| (e.id, e.server))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(titles("OWNER"), vec![(first, 5)]);
+        assert_eq!(titles("rtng"), vec![(second, 5), (second, 6)]);
+        // substring matches come before in-order ones
+        assert_eq!(titles("ro"), vec![(second, 5), (second, 6), (first, 5)]);
+        // however late in a long title
+        assert_eq!(titles("net"), vec![(second, 5), (second, 6), (third, 5)]);
+        assert!(titles("broken").is_empty());
+    }
+}
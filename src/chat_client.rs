@@ -1,3 +1,16 @@
+use crate::chat_message::{ChatEntry, ChatPayload, MessageStatus};
+use crate::client_directory::{ClientDirectory, DirectoryChange, DirectoryConfig};
+use crate::errors::ClientError;
+use crate::group::Group;
+use crate::history::{History, HistoryStore, MemoryHistoryStore};
+use crate::outbox::{Outbox, OutboxConfig, QueuedMessage};
+use crate::reorder::{ReorderBuffer, ReorderConfig};
+use crate::request_tracker::{Expiry, RequestTracker, RetryPolicy};
+use crate::server_health::ServerHealth;
+use crate::ticker::{TICK_INTERVAL, with_ticks};
+use crate::types::{
+    ChatClientCommand, ChatClientEvent, ClientErrorEvent, CorrelatedCommand, CorrelatedEvent,
+};
 use common::packet_processor::Processor;
 use common::types::{
     ChatCommand, ChatEvent, ChatRequest, ChatResponse, Command, Event, Message, NodeCommand,
@@ -6,9 +19,18 @@ use common::types::{
 use common::{FragmentAssembler, RoutingHandler};
 use crossbeam_channel::{Receiver, Sender};
 use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
+use std::time::{Instant, SystemTime};
+use uuid::Uuid;
 use wg_internal::packet::NodeType;
 use wg_internal::{network::NodeId, packet::Packet};
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Registration {
+    Pending,
+    Registered,
+}
+
 pub struct ChatClient {
     id: NodeId,
     routing_handler: RoutingHandler,
@@ -16,11 +38,22 @@ pub struct ChatClient {
     controller_send: Sender<Box<dyn Event>>,
     packet_recv: Receiver<Packet>,
     assembler: FragmentAssembler,
-    registered_clients: HashMap<NodeId, Vec<NodeId>>, // server, list of clients registered to that
-    // server
-    pending_requests: VecDeque<ChatRequest>,
+    registered_clients: ClientDirectory, // clients registered to each server
+    pending_requests: VecDeque<(ChatRequest, Option<u64>)>, // request, correlation id
     communication_servers: HashSet<NodeId>,
-    chats_history: HashMap<NodeId, Vec<Message>>,
+    chats_history: History,
+    history_store: Box<dyn HistoryStore>,
+    sent: HashMap<NodeId, u64>, // messages sent to each peer, numbering the next one
+    outbox: Outbox,             // messages waiting for a server that knows their recipient
+    reorder: ReorderBuffer<(Uuid, String)>, // received messages ahead of their turn
+    read_receipts: bool,
+    groups: HashMap<Uuid, Group>,
+    group_history: HashMap<Uuid, Vec<ChatEntry>>,
+    group_acks: HashMap<Uuid, HashSet<NodeId>>, // group message, members yet to acknowledge it
+    registrations: HashMap<NodeId, Registration>, // server, registration state
+    requests: RequestTracker<ChatRequest>,
+    server_health: ServerHealth,
+    correlation: Option<u64>, // command whose events are being sent
 }
 
 impl ChatClient {
@@ -38,171 +71,1162 @@ impl ChatClient {
         Self {
             id,
             routing_handler,
-            controller_recv,
+            controller_recv: with_ticks(controller_recv, TICK_INTERVAL, || {
+                Box::new(ChatClientCommand::Tick)
+            }),
             controller_send,
             packet_recv,
             assembler: FragmentAssembler::default(),
-            registered_clients: HashMap::new(),
+            registered_clients: ClientDirectory::new(DirectoryConfig::default()),
             communication_servers: HashSet::new(),
             chats_history: HashMap::new(),
+            history_store: Box::new(MemoryHistoryStore::default()),
+            sent: HashMap::new(),
+            read_receipts: true,
+            groups: HashMap::new(),
+            group_history: HashMap::new(),
+            group_acks: HashMap::new(),
             pending_requests: VecDeque::new(),
+            outbox: Outbox::new(OutboxConfig::default()),
+            reorder: ReorderBuffer::new(ReorderConfig::default()),
+            registrations: HashMap::new(),
+            requests: RequestTracker::new(RetryPolicy::default()),
+            server_health: ServerHealth::default(),
+            correlation: None,
+        }
+    }
+
+    /// Writes history through to `store`, reloading the conversations it holds.
+    pub fn with_history_store(
+        mut self,
+        mut store: Box<dyn HistoryStore>,
+    ) -> Result<Self, ClientError> {
+        self.chats_history = store.load()?;
+        self.sent = store.load_sent()?;
+        for (peer, next) in store.load_received()? {
+            self.reorder.start(peer, next);
         }
+        self.history_store = store;
+        Ok(self)
+    }
+
+    #[must_use]
+    pub fn with_read_receipts(mut self, enabled: bool) -> Self {
+        self.read_receipts = enabled;
+        self
+    }
+
+    #[must_use]
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.requests.set_policy(policy);
+        self
+    }
+
+    /// Forgets a chat server after `max_failures` consecutive client list
+    /// queries or registrations it did not answer in time, three by default.
+    #[must_use]
+    pub fn with_max_server_failures(mut self, max_failures: u32) -> Self {
+        self.server_health.set_max_failures(max_failures);
+        self
+    }
+
+    #[must_use]
+    pub fn with_directory_config(mut self, config: DirectoryConfig) -> Self {
+        self.registered_clients.set_config(config);
+        self
+    }
+
+    #[must_use]
+    pub fn with_outbox_config(mut self, config: OutboxConfig) -> Self {
+        self.outbox.set_config(config);
+        self
+    }
+
+    #[must_use]
+    pub fn with_reorder_config(mut self, config: ReorderConfig) -> Self {
+        self.reorder.set_config(config);
+        self
+    }
+
+    /// Keeps queued messages in the file at `path`, reloading the ones queued
+    /// before a restart.
+    pub fn with_outbox(mut self, path: impl Into<PathBuf>) -> Result<Self, ClientError> {
+        self.outbox = Outbox::open(path, self.outbox.config())?;
+        Ok(self)
     }
 
     fn get_chats_history(&self) -> HashMap<NodeId, Vec<Message>> {
-        self.chats_history.clone()
+        self.chats_history
+            .iter()
+            .map(|(k, chat)| (*k, chat.iter().map(|e| e.message.clone()).collect()))
+            .collect()
     }
 
-    fn add_list_of_registerd_clients(&mut self, server: NodeId, l: &[NodeId]) {
-        if let Some(list) = self.registered_clients.get_mut(&server) {
-            for n in l {
-                if !list.contains(n) {
-                    list.push(*n);
-                }
+    fn add_list_of_registerd_clients(&mut self, server: NodeId, l: &[NodeId]) -> bool {
+        let change = self.registered_clients.update(server, l, Instant::now());
+        self.notify_directory_change(server, change)
+    }
+
+    fn notify_directory_change(&self, server: NodeId, change: DirectoryChange) -> bool {
+        for client in change.joined {
+            if self.try_send(ChatClientEvent::ClientJoined {
+                notification_from: self.id,
+                server,
+                client,
+            }) {
+                return true;
             }
-        } else {
-            self.registered_clients.insert(server, l.to_vec());
         }
+        for client in change.left {
+            if self.try_send(ChatClientEvent::ClientLeft {
+                notification_from: self.id,
+                server,
+                client,
+            }) {
+                return true;
+            }
+        }
+        false
     }
 
-    fn get_registered_clients(&self) -> Vec<NodeId> {
-        let mut set = HashSet::new();
-        for slice in self.registered_clients.values() {
-            for n in slice {
-                set.insert(*n);
+    // drops the expired clients and queries the lists due for a refresh
+    fn refresh_directory(&mut self, now: Instant) -> bool {
+        for (server, client) in self.registered_clients.expire(now) {
+            if self.try_send(ChatClientEvent::ClientLeft {
+                notification_from: self.id,
+                server,
+                client,
+            }) {
+                return true;
+            }
+        }
+        for server in self.registered_clients.due_for_refresh(now) {
+            if self.communication_servers.contains(&server)
+                && let Err(e) = self.send_request(&ChatRequest::ClientListQuery, server)
+                && self.report(e)
+            {
+                return true;
             }
         }
-        set.iter().copied().collect()
+        false
+    }
+
+    fn get_registered_clients(&self) -> Vec<NodeId> {
+        self.registered_clients.clients()
     }
 
-    fn insert_message(&mut self, key: NodeId, message: Message) {
+    fn insert_message(&mut self, key: NodeId, entry: ChatEntry) {
+        if let Err(e) = self.history_store.append(key, &entry) {
+            let _ = self.report(e);
+        }
         if let Some(chat) = self.chats_history.get_mut(&key) {
-            chat.push(message);
+            chat.push(entry);
         } else {
-            self.chats_history.insert(key, vec![message]);
+            self.chats_history.insert(key, vec![entry]);
         }
     }
 
+    fn has_message(&self, key: NodeId, id: Uuid) -> bool {
+        self.chats_history
+            .get(&key)
+            .is_some_and(|chat| chat.iter().any(|e| e.id == id))
+    }
+
+    // statuses only move forward, except that unacknowledged messages can fail
+    fn set_status(&mut self, key: NodeId, id: Uuid, status: MessageStatus) -> bool {
+        fn rank(status: MessageStatus) -> u8 {
+            match status {
+                MessageStatus::Failed => 0,
+                MessageStatus::Queued => 1,
+                MessageStatus::Sent => 2,
+                MessageStatus::Delivered => 3,
+                MessageStatus::Read => 4,
+            }
+        }
+        let Some(entry) = self
+            .chats_history
+            .get_mut(&key)
+            .and_then(|chat| chat.iter_mut().find(|e| e.id == id))
+        else {
+            return false;
+        };
+        let allowed = if status == MessageStatus::Failed {
+            matches!(entry.status, MessageStatus::Queued | MessageStatus::Sent)
+        } else {
+            rank(status) > rank(entry.status)
+        };
+        if !allowed {
+            return false;
+        }
+        entry.status = status;
+        if let Err(e) = self.history_store.set_status(key, id, status)
+            && self.report(e)
+        {
+            return true;
+        }
+        self.try_send(ChatClientEvent::MessageStatusChanged {
+            notification_from: self.id,
+            peer: key,
+            id,
+            status,
+        })
+    }
+
+    fn is_registered(&self, server: NodeId) -> bool {
+        self.registrations.get(&server) == Some(&Registration::Registered)
+    }
+
+    // only servers we are registered with are allowed to carry our messages
     fn find_destination_by_client_id(&self, to: NodeId) -> Option<NodeId> {
-        for (s, l) in &self.registered_clients {
-            if l.contains(&to) {
-                return Some(*s);
+        self.registered_clients
+            .servers_of(to)
+            .into_iter()
+            .find(|s| self.is_registered(*s))
+    }
+
+    fn is_known_client(&self, to: NodeId) -> bool {
+        self.registered_clients.contains(to)
+    }
+
+    fn discover_servers(&mut self) -> bool {
+        let Some(servers) = self.routing_handler.get_servers() else {
+            return false;
+        };
+        for server in servers {
+            if let Err(e) = self.send_raw(&ChatRequest::ServerTypeQuery, server, None)
+                && self.report(e)
+            {
+                return true;
             }
         }
-        None
+        false
     }
 
-    fn discover_servers(&mut self) {
-        let req = ChatRequest::ServerTypeQuery;
-        if let Ok(req) = serde_json::to_vec(&req) {
-            if let Some(servers) = self.routing_handler.get_servers() {
-                for server in &servers {
-                    let _ = self.routing_handler.send_message(&req, *server, None);
-                }
+    fn broadcast(&mut self, req: &ChatRequest) -> bool {
+        if self.communication_servers.is_empty() {
+            self.pending_requests
+                .push_back((req.clone(), self.correlation));
+            return false;
+        }
+        let servers = self
+            .communication_servers
+            .iter()
+            .copied()
+            .collect::<Vec<_>>();
+        for server in servers {
+            let session_id =
+                self.requests
+                    .track(req.clone(), server, self.correlation, Instant::now());
+            if let Err(e) = self.send_raw(req, server, Some(session_id))
+                && self.report(e)
+            {
+                return true;
             }
         }
+        false
+    }
+
+    // records the number of the next message expected from `peer`, so that a
+    // restart does not wait on messages already received
+    fn persist_received(&mut self, peer: NodeId) {
+        if let Some(next) = self.reorder.next(peer)
+            && let Err(e) = self.history_store.set_received(peer, next)
+        {
+            let _ = self.report(e);
+        }
+    }
+
+    fn handle_send_message(&mut self, message: &Message) -> bool {
+        let id = Uuid::new_v4();
+        self.insert_message(
+            message.to,
+            ChatEntry::new(id, message.clone(), MessageStatus::Queued),
+        );
+        let payload = ChatPayload::Text {
+            id,
+            text: message.text.clone(),
+            seq: None,
+        };
+        self.deliver(message.to, payload.encode())
+    }
+
+    // numbers a text message as it leaves, so that the ones failed, refused or
+    // cancelled before leave no gap for the recipient to wait on; a message
+    // sent again keeps its number
+    fn number(&mut self, to: NodeId, payload: String) -> String {
+        let Some(ChatPayload::Text {
+            id,
+            text,
+            seq: None,
+        }) = ChatPayload::decode(&payload)
+        else {
+            return payload;
+        };
+        let sent = self.sent.entry(to).or_default();
+        let seq = *sent;
+        *sent += 1;
+        if let Err(e) = self.history_store.set_sent(to, seq + 1) {
+            let _ = self.report(e);
+        }
+        ChatPayload::Text {
+            id,
+            text,
+            seq: Some(seq),
+        }
+        .encode()
     }
 
-    fn broadcast(&mut self, req: &ChatRequest) {
-        if let Ok(ser_req) = serde_json::to_vec(&req) {
-            if self.communication_servers.is_empty() {
-                self.pending_requests.push_back(req.clone());
-                return;
+    // sends an encoded payload to a client, queueing it until a server that knows
+    // the client answers
+    fn deliver(&mut self, to: NodeId, payload: String) -> bool {
+        let id = ChatPayload::decode(&payload).map(|p| p.id());
+        if let Some(dest) = self.find_destination_by_client_id(to) {
+            let req = ChatRequest::MessageFor {
+                client_id: to,
+                message: self.number(to, payload),
+            };
+            // the request stays tracked, so a failed send is retried like a lost one
+            if let Err(e) = self.send_request(&req, dest) {
+                return self.report(e);
             }
-            for server in &self.communication_servers {
-                let _ = self.routing_handler.send_message(&ser_req, *server, None);
+            if self.try_send(ChatEvent::MessageSent {
+                notification_from: self.id,
+                to,
+            }) {
+                return true;
+            }
+            if let Some(id) = id {
+                return self.set_status(to, id, MessageStatus::Sent);
             }
+        } else if self.is_known_client(to) {
+            // leaves once registered with a server listing the recipient
+            return self.queue_message(to, payload);
+        } else {
+            return self.queue_message(to, payload)
+                || self.broadcast(&ChatRequest::ClientListQuery);
         }
+
+        false
     }
 
-    fn handle_send_message(&mut self, message: &Message) -> bool {
+    // keeps a message in the outbox, failing it when the outbox is full
+    fn queue_message(&mut self, to: NodeId, payload: String) -> bool {
+        self.enqueue(to, payload, Outbox::push)
+    }
+
+    // puts a message that was already in flight back ahead of the queued ones
+    fn requeue_message(&mut self, to: NodeId, payload: String) -> bool {
+        self.enqueue(to, payload, Outbox::push_front)
+    }
+
+    fn enqueue(
+        &mut self,
+        to: NodeId,
+        payload: String,
+        push: fn(&mut Outbox, QueuedMessage) -> bool,
+    ) -> bool {
+        let queued = QueuedMessage {
+            to,
+            id: ChatPayload::decode(&payload).map_or_else(Uuid::new_v4, |p| p.id()),
+            payload,
+            queued_at: SystemTime::now(),
+            correlation: self.correlation,
+        };
+        if push(&mut self.outbox, queued.clone()) {
+            return self.save_outbox();
+        }
+        if self.fail_message(to, &queued.payload) {
+            return true;
+        }
+        self.try_send(ChatClientEvent::MessageRefused {
+            notification_from: self.id,
+            to,
+            reason: "outbox full".to_string(),
+        })
+    }
+
+    fn save_outbox(&self) -> bool {
+        match self.outbox.save() {
+            Ok(()) => false,
+            Err(e) => self.report(e.into()),
+        }
+    }
+
+    // fails the messages queued for longer than the outbox allows
+    fn expire_outbox(&mut self) -> bool {
+        let expired = self.outbox.expire(SystemTime::now());
+        if expired.is_empty() {
+            return false;
+        }
+        if self.save_outbox() {
+            return true;
+        }
+        for queued in expired {
+            self.correlation = queued.correlation;
+            if self.fail_message(queued.to, &queued.payload) {
+                return true;
+            }
+            if self.try_send(ChatClientEvent::MessageExpired {
+                notification_from: self.id,
+                to: queued.to,
+                id: queued.id,
+            }) {
+                return true;
+            }
+        }
+        self.correlation = None;
+        false
+    }
+
+    fn handle_get_outbox(&self) -> bool {
+        self.try_send(ChatClientEvent::Outbox {
+            notification_from: self.id,
+            messages: self.outbox.messages(),
+        })
+    }
+
+    fn handle_cancel_queued(&mut self, id: Uuid) -> bool {
+        let cancelled = self.outbox.cancel(id);
+        if !cancelled.is_empty() && self.save_outbox() {
+            return true;
+        }
+        for queued in cancelled {
+            if self.fail_message(queued.to, &queued.payload) {
+                return true;
+            }
+        }
+        self.handle_get_outbox()
+    }
+
+    // sends again a message whose server was lost, without notifying it as a new
+    // one; it waits for the client lists when no other server knows the recipient
+    fn resend(&mut self, to: NodeId, req: ChatRequest) -> bool {
+        let Some(dest) = self.find_destination_by_client_id(to) else {
+            return match req {
+                ChatRequest::MessageFor { message, .. } => self.requeue_message(to, message),
+                _ => false,
+            };
+        };
+        match self.send_request(&req, dest) {
+            Ok(()) => false,
+            Err(e) => self.report(e),
+        }
+    }
+
+    // forgets a dead chat server, asks the others for their client lists and
+    // moves the messages in flight through it to the next server knowing their
+    // recipient
+    fn drop_server(&mut self, server: NodeId) -> bool {
+        self.communication_servers.remove(&server);
+        let left = DirectoryChange {
+            joined: vec![],
+            left: self.registered_clients.remove_server(server),
+        };
+        self.registrations.remove(&server);
+        let correlation = self.correlation;
+        self.correlation = None;
+        if self.try_send(ChatClientEvent::ServerLost {
+            notification_from: self.id,
+            server,
+        }) || self.notify_directory_change(server, left)
+            || self.broadcast(&ChatRequest::ClientListQuery)
+            || (self.communication_servers.is_empty() && self.discover_servers())
+        {
+            return true;
+        }
+        // oldest first, the ones going back to the outbox in reverse so that
+        // they stay in order ahead of the queued ones
+        let (sendable, queued): (Vec<_>, Vec<_>) = self
+            .requests
+            .resolve_correlated_by(|_, dest| dest == server)
+            .into_iter()
+            .filter_map(|(req, request_correlation)| match &req {
+                ChatRequest::MessageFor { client_id, .. } => {
+                    Some((*client_id, req, request_correlation))
+                }
+                _ => None,
+            })
+            .partition(|(to, ..)| self.find_destination_by_client_id(*to).is_some());
+        for (to, req, request_correlation) in sendable.into_iter().chain(queued.into_iter().rev()) {
+            self.correlation = request_correlation;
+            if self.resend(to, req) {
+                return true;
+            }
+        }
+        self.correlation = correlation;
+        false
+    }
+
+    // receipts are not acknowledged, so they are sent without being tracked
+    fn send_receipt(&mut self, server: NodeId, to: NodeId, payload: &ChatPayload) -> bool {
         let req = ChatRequest::MessageFor {
-            client_id: message.to,
-            message: message.text.clone(),
+            client_id: to,
+            message: payload.encode(),
         };
-        if let Some(dest) = self.find_destination_by_client_id(message.to) {
-            if let Ok(req) = serde_json::to_vec(&req) {
-                let _ = self.routing_handler.send_message(&req, dest, None);
+        match self.send_raw(&req, server, None) {
+            Ok(()) => false,
+            Err(e) => self.report(e),
+        }
+    }
 
-                if self
-                    .controller_send
-                    .send(Box::new(ChatEvent::MessageSent {
-                        notification_from: self.id,
-                        to: message.to,
-                    }))
-                    .is_err()
-                {
-                    return true;
+    fn receive_message(&mut self, client_id: NodeId, id: Uuid, text: String) {
+        let received = Message::new(client_id, self.id, text);
+        let _ = self.try_send(ChatEvent::MessageReceived {
+            notification_from: self.id,
+            msg: received.clone(),
+        });
+        self.insert_message(
+            client_id,
+            ChatEntry::new(id, received, MessageStatus::Delivered),
+        );
+    }
+
+    fn handle_message_from(&mut self, server: NodeId, client_id: NodeId, message: String) {
+        match ChatPayload::decode(&message) {
+            Some(ChatPayload::Text { id, text, seq }) => {
+                // acknowledge retransmissions too, the previous ack may have been lost
+                self.send_receipt(server, client_id, &ChatPayload::Delivered { id });
+                if self.has_message(client_id, id) {
+                    return;
+                }
+                let Some(seq) = seq else {
+                    self.receive_message(client_id, id, text);
+                    return;
+                };
+                // a peer never heard from numbers its first message zero
+                self.reorder.start(client_id, 0);
+                let in_order = self
+                    .reorder
+                    .push(client_id, seq, (id, text), Instant::now());
+                if !in_order.is_empty() {
+                    self.persist_received(client_id);
+                }
+                for (id, text) in in_order {
+                    if !self.has_message(client_id, id) {
+                        self.receive_message(client_id, id, text);
+                    }
                 }
-                self.insert_message(message.to, message.clone());
             }
-        } else {
-            self.pending_requests.push_back(req);
-            self.broadcast(&ChatRequest::ClientListQuery);
+            Some(ChatPayload::Delivered { id }) => {
+                self.resolve_answered(|r, _| is_message_to(r, id, client_id));
+                let _ = self.acknowledge_group_message(id, client_id);
+                let _ = self.set_status(client_id, id, MessageStatus::Delivered);
+            }
+            Some(ChatPayload::Read { id }) => {
+                self.resolve_answered(|r, _| is_message_to(r, id, client_id));
+                let _ = self.set_status(client_id, id, MessageStatus::Read);
+            }
+            Some(ChatPayload::GroupInvite {
+                group_id,
+                name,
+                members,
+            }) => {
+                // invites are acknowledged like messages, under the group id
+                self.send_receipt(server, client_id, &ChatPayload::Delivered { id: group_id });
+                self.join_group(Group::new(group_id, name, members));
+            }
+            Some(ChatPayload::GroupText { id, group_id, text }) => {
+                self.send_receipt(server, client_id, &ChatPayload::Delivered { id });
+                self.receive_group_message(client_id, id, group_id, text);
+            }
+            // plain text from clients that do not speak the payload protocol
+            None => self.receive_message(client_id, Uuid::new_v4(), message),
         }
+    }
 
+    fn handle_mark_read(&mut self, peer: NodeId) -> bool {
+        let unread = self
+            .chats_history
+            .get(&peer)
+            .map(|chat| {
+                chat.iter()
+                    .filter(|e| e.message.from == peer && e.status == MessageStatus::Delivered)
+                    .map(|e| e.id)
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let server = self.find_destination_by_client_id(peer);
+        for id in unread {
+            if self.set_status(peer, id, MessageStatus::Read) {
+                return true;
+            }
+            if self.read_receipts
+                && let Some(server) = server
+                && self.send_receipt(server, peer, &ChatPayload::Read { id })
+            {
+                return true;
+            }
+        }
         false
     }
 
-    fn send_request(&mut self, req: &ChatRequest, dest: NodeId) {
-        if let Ok(ser) = serde_json::to_vec(&req) {
-            let _ = self.routing_handler.send_message(&ser, dest, None);
+    fn handle_get_history_with_status(&self) -> bool {
+        self.try_send(ChatClientEvent::HistoryWithStatus {
+            notification_from: self.id,
+            history: self.chats_history.clone(),
+        })
+    }
+
+    // notifies the controller only when the group is new or changed, since
+    // retransmitted invites are received again
+    fn join_group(&mut self, group: Group) {
+        let group = match self.groups.get_mut(&group.id) {
+            Some(known) => {
+                let added = known.add_members(&group.members);
+                // a group first seen through one of its messages has no name yet
+                let named = known.name.is_empty() && !group.name.is_empty();
+                if named {
+                    known.name = group.name;
+                }
+                if added.is_empty() && !named {
+                    return;
+                }
+                known.clone()
+            }
+            None => {
+                self.groups.insert(group.id, group.clone());
+                group
+            }
+        };
+        let _ = self.try_send(ChatClientEvent::GroupJoined {
+            notification_from: self.id,
+            group,
+        });
+    }
+
+    fn receive_group_message(&mut self, sender: NodeId, id: Uuid, group_id: Uuid, text: String) {
+        // the invite may still be on its way
+        self.groups
+            .entry(group_id)
+            .or_insert_with(|| Group::new(group_id, String::new(), vec![sender, self.id]));
+        let chat = self.group_history.entry(group_id).or_default();
+        if chat.iter().any(|e| e.id == id) {
+            return;
         }
+        let received = Message::new(sender, self.id, text);
+        chat.push(ChatEntry::new(
+            id,
+            received.clone(),
+            MessageStatus::Delivered,
+        ));
+        let _ = self.try_send(ChatClientEvent::GroupMessageReceived {
+            notification_from: self.id,
+            group_id,
+            msg: received,
+        });
     }
 
-    fn handle_get_clients_list(&mut self) -> bool {
-        if self.registered_clients.is_empty() {
-            self.broadcast(&ChatRequest::ClientListQuery);
-        } else if self
-            .controller_send
-            .send(Box::new(ChatEvent::RegisteredClients {
-                notification_from: self.id,
-                list: self.get_registered_clients(),
-            }))
-            .is_err()
+    fn acknowledge_group_message(&mut self, id: Uuid, member: NodeId) -> bool {
+        let Some(waiting) = self.group_acks.get_mut(&id) else {
+            return false;
+        };
+        waiting.remove(&member);
+        if !waiting.is_empty() {
+            return false;
+        }
+        self.group_acks.remove(&id);
+        self.set_group_status(id, member, MessageStatus::Delivered)
+    }
+
+    // settles a group message, `member` being the one whose copy settled it
+    fn set_group_status(&mut self, id: Uuid, member: NodeId, status: MessageStatus) -> bool {
+        let Some(entry) = self
+            .group_history
+            .values_mut()
+            .flat_map(|chat| chat.iter_mut())
+            .find(|e| e.id == id)
+        else {
+            return false;
+        };
+        entry.status = status;
+        self.try_send(ChatClientEvent::MessageStatusChanged {
+            notification_from: self.id,
+            peer: member,
+            id,
+            status,
+        })
+    }
+
+    fn group_error(&self, group_id: Uuid, reason: &str) -> bool {
+        self.try_send(ChatClientEvent::GroupError {
+            notification_from: self.id,
+            group_id,
+            reason: reason.to_string(),
+        })
+    }
+
+    fn handle_create_group(&mut self, name: &str, members: &[NodeId]) -> bool {
+        let group = Group::new(Uuid::new_v4(), name.to_string(), vec![self.id]);
+        let group_id = group.id;
+        self.groups.insert(group_id, group.clone());
+        if self.try_send(ChatClientEvent::GroupJoined {
+            notification_from: self.id,
+            group,
+        }) {
+            return true;
+        }
+        self.handle_invite_to_group(group_id, members)
+    }
+
+    // only clients listed by some chat server can be invited
+    fn handle_invite_to_group(&mut self, group_id: Uuid, members: &[NodeId]) -> bool {
+        let registered = self.get_registered_clients();
+        let (valid, unknown): (Vec<NodeId>, Vec<NodeId>) =
+            members.iter().partition(|m| registered.contains(m));
+        if !unknown.is_empty()
+            && self.group_error(group_id, &format!("unknown clients: {unknown:?}"))
         {
             return true;
         }
+        let Some(group) = self.groups.get_mut(&group_id) else {
+            return self.group_error(group_id, "unknown group");
+        };
+        if group.add_members(&valid).is_empty() {
+            return false;
+        }
+        let group = group.clone();
+        let invite = ChatPayload::GroupInvite {
+            group_id,
+            name: group.name.clone(),
+            members: group.members.clone(),
+        }
+        .encode();
+        for member in group.recipients(self.id) {
+            if self.deliver(member, invite.clone()) {
+                return true;
+            }
+        }
+        false
+    }
+
+    // fans the message out to every member, through whichever server knows each one
+    fn handle_send_group_message(&mut self, group_id: Uuid, text: &str) -> bool {
+        let Some(group) = self.groups.get(&group_id) else {
+            return self.group_error(group_id, "unknown group");
+        };
+        let recipients = group.recipients(self.id);
+        let id = Uuid::new_v4();
+        let sent = Message::new(self.id, self.id, text.to_string());
+        self.group_history
+            .entry(group_id)
+            .or_default()
+            .push(ChatEntry::new(id, sent, MessageStatus::Sent));
+        self.group_acks.insert(id, recipients.clone());
+        let payload = ChatPayload::GroupText {
+            id,
+            group_id,
+            text: text.to_string(),
+        }
+        .encode();
+        for member in recipients {
+            if self.deliver(member, payload.clone()) {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn handle_get_group_history(&self, group_id: Uuid) -> bool {
+        self.try_send(ChatClientEvent::GroupHistory {
+            notification_from: self.id,
+            group_id,
+            history: self
+                .group_history
+                .get(&group_id)
+                .cloned()
+                .unwrap_or_default(),
+        })
+    }
+
+    // marks every in-flight message to `client_id` as failed, each under the
+    // command that sent it, leaving the first one correlated
+    fn fail_messages_to(&mut self, client_id: NodeId) -> bool {
+        let in_flight = self.requests.resolve_correlated_by(
+            |r, _| matches!(r, ChatRequest::MessageFor { client_id: c, .. } if *c == client_id),
+        );
+        let correlation = in_flight.iter().find_map(|(_, c)| *c);
+        for (req, request_correlation) in in_flight {
+            self.correlation = request_correlation;
+            if let ChatRequest::MessageFor { message, .. } = &req
+                && self.fail_message(client_id, message)
+            {
+                return true;
+            }
+        }
+        self.correlation = correlation;
         false
     }
 
+    // stops tracking the requests a response answers, correlating what follows
+    // with the command that sent them rather than with the response's session
+    fn resolve_answered(&mut self, pred: impl Fn(&ChatRequest, NodeId) -> bool) {
+        self.correlation = self
+            .requests
+            .resolve_correlated_by(pred)
+            .into_iter()
+            .find_map(|(_, correlation)| correlation);
+    }
+
+    // a text message fails in its conversation, a group message fails as a whole
+    // as soon as one of its copies does
+    fn fail_message(&mut self, to: NodeId, payload: &str) -> bool {
+        match ChatPayload::decode(payload) {
+            Some(ChatPayload::Text { id, .. }) => self.set_status(to, id, MessageStatus::Failed),
+            Some(ChatPayload::GroupText { id, .. }) => {
+                self.group_acks.remove(&id).is_some()
+                    && self.set_group_status(id, to, MessageStatus::Failed)
+            }
+            _ => false,
+        }
+    }
+
+    fn send_raw(
+        &mut self,
+        req: &ChatRequest,
+        dest: NodeId,
+        session_id: Option<u64>,
+    ) -> Result<(), ClientError> {
+        let ser = serde_json::to_vec(&req).map_err(|_| ClientError::SerializationError)?;
+        self.routing_handler
+            .send_message(&ser, dest, session_id)
+            .map_err(|error| ClientError::RoutingError {
+                destination: dest,
+                error,
+            })
+    }
+
+    fn send_request(&mut self, req: &ChatRequest, dest: NodeId) -> Result<(), ClientError> {
+        let session_id = self
+            .requests
+            .track(req.clone(), dest, self.correlation, Instant::now());
+        self.send_raw(req, dest, Some(session_id))
+    }
+
+    // retries or fails every request whose response is overdue; chat requests are
+    // addressed to a specific server, so a re-route resends through a fresh route
+    // to the same destination
+    fn check_timeouts(&mut self) -> bool {
+        self.correlation = None;
+        let now = Instant::now();
+        if self.refresh_directory(now) || self.expire_outbox() {
+            return true;
+        }
+        // the messages still missing are given up on, the later ones shown
+        let mut skipped = HashSet::new();
+        for (peer, (id, text)) in self.reorder.expire(now) {
+            if skipped.insert(peer) {
+                self.persist_received(peer);
+            }
+            if !self.has_message(peer, id) {
+                self.receive_message(peer, id, text);
+            }
+        }
+        for expiry in self.requests.poll(now) {
+            match expiry {
+                Expiry::Retry {
+                    session_id,
+                    request,
+                    destination,
+                } => {
+                    self.correlation = self.requests.correlation(session_id);
+                    if let Err(e) = self.send_raw(&request, destination, Some(session_id))
+                        && self.report(e)
+                    {
+                        return true;
+                    }
+                }
+                Expiry::Reroute {
+                    session_id,
+                    request,
+                    destination,
+                } => {
+                    self.correlation = self.requests.correlation(session_id);
+                    let destination = self.reroute(&request, destination);
+                    self.requests.retarget(session_id, destination);
+                    if let Err(e) = self.send_raw(&request, destination, Some(session_id))
+                        && self.report(e)
+                    {
+                        return true;
+                    }
+                }
+                Expiry::TimedOut {
+                    request,
+                    destination,
+                    correlation,
+                } => {
+                    self.correlation = correlation;
+                    // only the requests a server answers itself tell whether it
+                    // is alive, a message also waiting on its recipient
+                    if matches!(
+                        request,
+                        ChatRequest::ClientListQuery | ChatRequest::RegistrationToChat { .. }
+                    ) && self.communication_servers.contains(&destination)
+                        && self.server_health.record_failure(destination)
+                        && self.drop_server(destination)
+                    {
+                        return true;
+                    }
+                    if matches!(request, ChatRequest::RegistrationToChat { .. }) {
+                        self.registrations.remove(&destination);
+                    }
+                    if let ChatRequest::MessageFor { client_id, message } = &request
+                        && self.fail_message(*client_id, message)
+                    {
+                        return true;
+                    }
+                    if self.try_send(ChatClientEvent::RequestTimedOut {
+                        notification_from: self.id,
+                        server: destination,
+                        request,
+                    }) {
+                        return true;
+                    }
+                }
+            }
+        }
+        self.correlation = None;
+        false
+    }
+
+    // a message can move to another registered server knowing its recipient,
+    // the other requests are about their server and stay with it
+    fn reroute(&self, request: &ChatRequest, destination: NodeId) -> NodeId {
+        let ChatRequest::MessageFor { client_id, .. } = request else {
+            return destination;
+        };
+        self.registered_clients
+            .servers_of(*client_id)
+            .into_iter()
+            .find(|s| *s != destination && self.is_registered(*s))
+            .unwrap_or(destination)
+    }
+
+    // wraps the event when it results from a `CorrelatedCommand`
+    fn try_send<E: Event + 'static>(&self, event: E) -> bool {
+        let event: Box<dyn Event> = match self.correlation {
+            Some(correlation_id) => Box::new(CorrelatedEvent {
+                correlation_id,
+                event: Box::new(event),
+            }),
+            None => Box::new(event),
+        };
+        self.controller_send.send(event).is_err()
+    }
+
+    // reports a failure that would otherwise be dropped, true when the controller is gone
+    fn report(&self, error: ClientError) -> bool {
+        self.try_send(ClientErrorEvent {
+            notification_from: self.id,
+            error,
+        })
+    }
+
+    fn handle_register(&mut self, server: Option<NodeId>) -> bool {
+        let targets: Vec<NodeId> = match server {
+            Some(s) => vec![s],
+            None => self.communication_servers.iter().copied().collect(),
+        };
+        if targets.is_empty() {
+            // no chat server known yet, register as soon as one answers
+            self.pending_requests.push_back((
+                ChatRequest::RegistrationToChat { client_id: self.id },
+                self.correlation,
+            ));
+            return self.discover_servers();
+        }
+        for server in targets {
+            if self.is_registered(server) {
+                continue;
+            }
+            let result = if self.communication_servers.contains(&server) {
+                self.send_request(
+                    &ChatRequest::RegistrationToChat { client_id: self.id },
+                    server,
+                )
+            } else {
+                Err(ClientError::UnknownServer)
+            };
+            match result {
+                Ok(()) => {
+                    self.registrations.insert(server, Registration::Pending);
+                }
+                Err(e) => {
+                    if self.try_send(ChatClientEvent::RegistrationFailed {
+                        notification_from: self.id,
+                        server,
+                        reason: e.to_string(),
+                    }) {
+                        return true;
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    // the protocol has no leave request, so unregistering only stops us from
+    // routing messages through the server
+    fn handle_unregister(&mut self, server: Option<NodeId>) -> bool {
+        let targets: Vec<NodeId> = match server {
+            Some(s) => vec![s],
+            None => self.registrations.keys().copied().collect(),
+        };
+        for server in targets {
+            if self.registrations.remove(&server).is_some()
+                && self.try_send(ChatClientEvent::Unregistered {
+                    notification_from: self.id,
+                    server,
+                })
+            {
+                return true;
+            }
+        }
+        false
+    }
+
+    // lists older than the refresh period are queried again, the clients being
+    // sent once they arrive
+    fn handle_get_clients_list(&mut self) -> bool {
+        if self.registered_clients.is_empty() || !self.registered_clients.is_fresh(Instant::now()) {
+            return self.broadcast(&ChatRequest::ClientListQuery);
+        }
+        self.try_send(ChatEvent::RegisteredClients {
+            notification_from: self.id,
+            list: self.get_registered_clients(),
+        })
+    }
+
+    // queued messages only leave once a server that registered the client
+    // lists their recipient, in the order they were queued
     fn try_send_pending_requests(&mut self) {
-        let pending_requests = self
-            .pending_requests
-            .drain(..)
-            .rev()
-            .collect::<Vec<ChatRequest>>();
-        for p in &pending_requests {
+        let pending_requests = self.pending_requests.drain(..).collect::<Vec<_>>();
+        let correlation = self.correlation;
+        for (p, request_correlation) in &pending_requests {
+            self.correlation = *request_correlation;
             match p {
-                ChatRequest::ClientListQuery => self.broadcast(p),
-                ChatRequest::MessageFor { client_id, message } => {
-                    let _ = self.handle_send_message(&Message::new(
-                        self.id,
-                        *client_id,
-                        message.clone(),
-                    ));
+                ChatRequest::ClientListQuery => {
+                    let _ = self.broadcast(p);
+                }
+                ChatRequest::RegistrationToChat { .. } => {
+                    let _ = self.handle_register(None);
                 }
                 _ => {}
             }
         }
+        let mut sent = false;
+        for to in self.outbox.destinations() {
+            if self.find_destination_by_client_id(to).is_none() {
+                continue;
+            }
+            sent = true;
+            for queued in self.outbox.take(to) {
+                self.correlation = queued.correlation;
+                let _ = self.deliver(to, queued.payload);
+            }
+        }
+        if sent {
+            let _ = self.save_outbox();
+        }
+        self.correlation = correlation;
     }
 
     fn handle_get_chats_history(&mut self) -> bool {
-        let history = self.get_chats_history();
-        if self
-            .controller_send
-            .send(Box::new(ChatEvent::ChatHistory {
-                notification_from: self.id,
-                history,
-            }))
-            .is_err()
-        {
-            return true;
+        self.try_send(ChatEvent::ChatHistory {
+            notification_from: self.id,
+            history: self.get_chats_history(),
+        })
+    }
+
+    fn handle_response(&mut self, response: ChatResponse, from: NodeId) {
+        match response {
+            ChatResponse::ServerType { server_type } => {
+                if matches!(server_type, ServerType::ChatServer) {
+                    self.communication_servers.insert(from);
+                    self.try_send_pending_requests();
+                    // messages reloaded after a restart wait for the client lists
+                    if !self.outbox.is_empty()
+                        && let Err(e) = self.send_request(&ChatRequest::ClientListQuery, from)
+                    {
+                        let _ = self.report(e);
+                    }
+                }
+            }
+            ChatResponse::ClientList { list_of_client_ids } => {
+                self.resolve_answered(|r, dest| {
+                    matches!(r, ChatRequest::ClientListQuery) && dest == from
+                });
+                if self.add_list_of_registerd_clients(from, &list_of_client_ids) {
+                    return;
+                }
+                let _ = self.try_send(ChatEvent::RegisteredClients {
+                    notification_from: self.id,
+                    list: self.get_registered_clients(),
+                });
+                self.try_send_pending_requests();
+            }
+            ChatResponse::MessageFrom { client_id, message } => {
+                self.handle_message_from(from, client_id, message);
+            }
+            ChatResponse::ErrorWrongClientId { wrong_id } => {
+                let _ = self.fail_messages_to(wrong_id);
+                let _ = self.try_send(ChatEvent::ErrorClientNotFound {
+                    notification_from: self.id,
+                    location: from,
+                    not_found: wrong_id,
+                });
+            }
+            // only a node that identified itself as a chat server can register us
+            ChatResponse::RegistrationSuccess if !self.communication_servers.contains(&from) => {
+                let _ = self.report(ClientError::UnknownSender(from));
+            }
+            ChatResponse::RegistrationSuccess => {
+                self.resolve_answered(|r, dest| {
+                    matches!(r, ChatRequest::RegistrationToChat { .. }) && dest == from
+                });
+                self.registrations.insert(from, Registration::Registered);
+                let _ = self.try_send(ChatEvent::RegistrationSucceeded {
+                    notification_from: self.id,
+                    to: from,
+                });
+                self.try_send_pending_requests();
+            }
         }
-        false
     }
 }
 
+// id of the text message carried by a `MessageFor` request
+fn payload_id(req: &ChatRequest) -> Option<Uuid> {
+    match req {
+        ChatRequest::MessageFor { message, .. } => message_id(message),
+        _ => None,
+    }
+}
+
+// id of the text message in an encoded payload, receipts and invites have none
+fn message_id(payload: &str) -> Option<Uuid> {
+    match ChatPayload::decode(payload) {
+        Some(ChatPayload::Text { id, .. } | ChatPayload::GroupText { id, .. }) => Some(id),
+        _ => None,
+    }
+}
+
+// id a `MessageFor` request is acknowledged with, the group id for invites
+fn acked_id(req: &ChatRequest) -> Option<Uuid> {
+    match req {
+        ChatRequest::MessageFor { message, .. } => match ChatPayload::decode(message) {
+            Some(ChatPayload::GroupInvite { group_id, .. }) => Some(group_id),
+            _ => message_id(message),
+        },
+        _ => None,
+    }
+}
+
+// group messages share their id across members, so acks also match the recipient
+fn is_message_to(req: &ChatRequest, id: Uuid, to: NodeId) -> bool {
+    matches!(req, ChatRequest::MessageFor { client_id, .. } if *client_id == to)
+        && acked_id(req) == Some(id)
+}
+
 impl Processor for ChatClient {
     fn controller_recv(&self) -> &Receiver<Box<dyn Command>> {
         &self.controller_recv
@@ -221,7 +1245,17 @@ impl Processor for ChatClient {
     }
 
     fn handle_command(&mut self, cmd: Box<dyn Command>) -> bool {
-        let cmd = cmd.into_any();
+        if self.check_timeouts() {
+            return true;
+        }
+        let (correlation, cmd) = match cmd.into_any().downcast::<CorrelatedCommand>() {
+            Ok(correlated) => (
+                Some(correlated.correlation_id),
+                correlated.command.into_any(),
+            ),
+            Err(cmd) => (None, cmd),
+        };
+        self.correlation = correlation;
         if let Some(cmd) = cmd.downcast_ref::<ChatCommand>() {
             match cmd {
                 ChatCommand::GetChatsHistory => return self.handle_get_chats_history(),
@@ -230,6 +1264,30 @@ impl Processor for ChatClient {
                     return self.handle_send_message(message);
                 }
             }
+        } else if let Some(cmd) = cmd.downcast_ref::<ChatClientCommand>() {
+            match cmd {
+                ChatClientCommand::Register(server) => return self.handle_register(*server),
+                ChatClientCommand::Unregister(server) => return self.handle_unregister(*server),
+                ChatClientCommand::Tick => {}
+                ChatClientCommand::MarkRead(peer) => return self.handle_mark_read(*peer),
+                ChatClientCommand::GetHistoryWithStatus => {
+                    return self.handle_get_history_with_status();
+                }
+                ChatClientCommand::CreateGroup { name, members } => {
+                    return self.handle_create_group(name, members);
+                }
+                ChatClientCommand::InviteToGroup { group_id, members } => {
+                    return self.handle_invite_to_group(*group_id, members);
+                }
+                ChatClientCommand::SendGroupMessage { group_id, text } => {
+                    return self.handle_send_group_message(*group_id, text);
+                }
+                ChatClientCommand::GetGroupHistory(group_id) => {
+                    return self.handle_get_group_history(*group_id);
+                }
+                ChatClientCommand::GetOutbox => return self.handle_get_outbox(),
+                ChatClientCommand::CancelQueued(id) => return self.handle_cancel_queued(*id),
+            }
         } else if let Some(cmd) = cmd.downcast_ref::<NodeCommand>() {
             match cmd {
                 NodeCommand::AddSender(node_id, sender) => {
@@ -239,6 +1297,9 @@ impl Processor for ChatClient {
                     self.routing_handler.remove_neighbor(*node_id);
                 }
                 NodeCommand::Shutdown => {
+                    if let Err(e) = self.history_store.flush() {
+                        let _ = self.report(e);
+                    }
                     return true;
                 }
             }
@@ -246,68 +1307,37 @@ impl Processor for ChatClient {
         false
     }
 
+    // a disconnected controller also stops the command loop, so message handling
+    // carries on regardless of whether its notifications went through
     fn handle_msg(&mut self, msg: Vec<u8>, from: NodeId, _session_id: u64) {
-        let _ = self
-            .controller_send
-            .send(Box::new(NodeEvent::MessageReceived {
-                notification_from: self.id,
-                from,
-            }));
-        if let Ok(msg) = serde_json::from_slice::<ChatResponse>(&msg) {
-            match msg {
-                ChatResponse::ServerType { server_type } => {
-                    if matches!(server_type, ServerType::ChatServer) {
-                        self.communication_servers.insert(from);
-                        self.try_send_pending_requests();
-                    }
-                }
-                ChatResponse::ClientList { list_of_client_ids } => {
-                    self.add_list_of_registerd_clients(from, &list_of_client_ids);
-                    let _ = self
-                        .controller_send
-                        .send(Box::new(ChatEvent::RegisteredClients {
-                            notification_from: self.id,
-                            list: self.get_registered_clients(),
-                        }));
-                    self.try_send_pending_requests();
-                }
-                ChatResponse::MessageFrom { client_id, message } => {
-                    let received = Message::new(client_id, self.id, message);
-                    let _ = self
-                        .controller_send
-                        .send(Box::new(ChatEvent::MessageReceived {
-                            notification_from: self.id,
-                            msg: received.clone(),
-                        }));
-                    self.insert_message(client_id, received);
-                }
-                ChatResponse::ErrorWrongClientId { wrong_id } => {
-                    let _ = self
-                        .controller_send
-                        .send(Box::new(ChatEvent::ErrorClientNotFound {
-                            notification_from: self.id,
-                            location: from,
-                            not_found: wrong_id,
-                        }));
-                }
-                ChatResponse::RegistrationSuccess => {
-                    let _ = self
-                        .controller_send
-                        .send(Box::new(ChatEvent::RegistrationSucceeded {
-                            notification_from: self.id,
-                            to: from,
-                        }));
-                }
+        self.correlation = None;
+        if self.communication_servers.contains(&from) {
+            self.server_health.record_success(from);
+        }
+        let _ = self.try_send(NodeEvent::MessageReceived {
+            notification_from: self.id,
+            from,
+        });
+        match serde_json::from_slice::<ChatResponse>(&msg) {
+            Ok(response) => self.handle_response(response, from),
+            Err(e) => {
+                let _ = self.report(ClientError::DeserializationError {
+                    from,
+                    reason: e.to_string(),
+                });
             }
         }
+        let _ = self.check_timeouts();
     }
 }
 
 #[cfg(test)]
 mod chat_client_tests {
     use super::*;
+    use crate::history::FileHistoryStore;
     use common::types::{ChatResponse, Message, ServerType};
     use crossbeam::channel::unbounded;
+    use std::time::Duration;
 
     fn create_test_chat_client() -> ChatClient {
         let (_controller_send, controller_recv) = unbounded();
@@ -318,6 +1348,24 @@ mod chat_client_tests {
         ChatClient::new(1, neighbors, packet_recv, controller_recv, event_send)
     }
 
+    fn create_test_chat_client_with_events() -> (ChatClient, Receiver<Box<dyn Event>>) {
+        let (_controller_send, controller_recv) = unbounded();
+        let (event_send, event_recv) = unbounded();
+        let (_, packet_recv) = unbounded();
+        let neighbors = HashMap::new();
+
+        let client = ChatClient::new(1, neighbors, packet_recv, controller_recv, event_send);
+        (client, event_recv)
+    }
+
+    fn message_from(client_id: NodeId, payload: &ChatPayload) -> Vec<u8> {
+        serde_json::to_vec(&ChatResponse::MessageFrom {
+            client_id,
+            message: payload.encode(),
+        })
+        .unwrap()
+    }
+
     #[test]
     /// Tests `ServerType` response handling (chat server being added to `HashSet`)
     fn test_server_type_response_handling() {
@@ -343,11 +1391,34 @@ mod chat_client_tests {
         let serialized = serde_json::to_vec(&response).unwrap();
         client.handle_msg(serialized, 5, 101);
 
-        assert_eq!(client.registered_clients.len(), 1);
-        assert!(client.registered_clients.contains_key(&5));
-        assert!(client.registered_clients.get(&5).unwrap().contains(&10));
-        assert!(client.registered_clients.get(&5).unwrap().contains(&11));
-        assert!(client.registered_clients.get(&5).unwrap().contains(&12));
+        assert_eq!(client.registered_clients.clients_of(5), vec![10, 11, 12]);
+    }
+
+    #[test]
+    /// Tests that a new client list replaces the previous one, notifying the
+    /// clients that joined and left
+    fn test_client_list_changes() {
+        let (mut client, events) = create_test_chat_client_with_events();
+        for (list, session) in [(vec![10, 11], 110), (vec![11, 12], 111)] {
+            let response = ChatResponse::ClientList {
+                list_of_client_ids: list,
+            };
+            client.handle_msg(serde_json::to_vec(&response).unwrap(), 5, session);
+        }
+        assert_eq!(client.get_registered_clients(), vec![11, 12]);
+
+        let changes = events
+            .try_iter()
+            .filter_map(|e| match e.as_any().downcast_ref::<ChatClientEvent>() {
+                Some(ChatClientEvent::ClientJoined { client, .. }) => Some((true, *client)),
+                Some(ChatClientEvent::ClientLeft { client, .. }) => Some((false, *client)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            vec![(true, 10), (true, 11), (true, 12), (false, 10)]
+        );
     }
 
     #[test]
@@ -365,9 +1436,9 @@ mod chat_client_tests {
         assert!(client.chats_history.contains_key(&20));
         let messages = client.chats_history.get(&20).unwrap();
         assert_eq!(messages.len(), 1);
-        assert_eq!(messages[0].from, 20);
-        assert_eq!(messages[0].to, 1);
-        assert_eq!(messages[0].text, "Hello from client 20".to_string());
+        assert_eq!(messages[0].message.from, 20);
+        assert_eq!(messages[0].message.to, 1);
+        assert_eq!(messages[0].message.text, "Hello from client 20".to_string());
     }
 
     #[test]
@@ -379,9 +1450,14 @@ mod chat_client_tests {
         let should_continue = !client.handle_command(Box::new(cmd)); // request put in pending
         assert!(should_continue, "Continued after GetRegisteredClients");
 
-        client.registered_clients.insert(2, vec![10, 11]);
+        client
+            .registered_clients
+            .update(2, &[10, 11], Instant::now());
         let message = Message::new(1, 10, "Test message".to_string());
-        client.insert_message(10, message);
+        client.insert_message(
+            10,
+            ChatEntry::new(Uuid::from_u128(1), message, MessageStatus::Sent),
+        );
 
         let cmd = ChatCommand::GetChatsHistory;
         let should_not_continue = client.handle_command(Box::new(cmd));
@@ -389,7 +1465,628 @@ mod chat_client_tests {
 
         let message = Message::new(1, 10, "Outgoing message".to_string());
         let cmd = ChatCommand::SendMessage(message);
-        let should_not_continue = client.handle_command(Box::new(cmd));
-        assert!(should_not_continue, "Continued after SendMessage");
+        let should_continue = !client.handle_command(Box::new(cmd)); // queued until registered
+        assert!(should_continue, "Stopped after SendMessage");
+        assert_eq!(client.outbox.len(), 1);
+    }
+
+    #[test]
+    /// Tests that history written to a file store is reloaded by a new client
+    fn test_history_store_reload() {
+        let path = std::env::temp_dir().join("client-chat-history-reload.jsonl");
+        let _ = std::fs::remove_file(&path);
+        let store = FileHistoryStore::open(&path).unwrap();
+        let mut client = create_test_chat_client()
+            .with_history_store(Box::new(store))
+            .unwrap();
+
+        let response = ChatResponse::MessageFrom {
+            client_id: 20,
+            message: "Persisted".to_string(),
+        };
+        client.handle_msg(serde_json::to_vec(&response).unwrap(), 5, 105);
+        client.handle_command(Box::new(NodeCommand::Shutdown));
+
+        let store = FileHistoryStore::open(&path).unwrap();
+        let client = create_test_chat_client()
+            .with_history_store(Box::new(store))
+            .unwrap();
+        assert_eq!(client.chats_history.get(&20).unwrap().len(), 1);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    /// Tests `Register` command and `RegistrationSuccess` handling
+    fn test_registration() {
+        let mut client = create_test_chat_client();
+        client.communication_servers.insert(5);
+
+        let should_continue = !client.handle_command(Box::new(ChatClientCommand::Register(None)));
+        assert!(should_continue);
+        assert_eq!(client.registrations.get(&5), Some(&Registration::Pending));
+
+        let serialized = serde_json::to_vec(&ChatResponse::RegistrationSuccess).unwrap();
+        client.handle_msg(serialized, 5, 103);
+        assert!(client.is_registered(5));
+
+        let _ = client.handle_command(Box::new(ChatClientCommand::Unregister(Some(5))));
+        assert!(!client.is_registered(5));
+    }
+
+    #[test]
+    /// Tests that a `ClientList` response stops tracking the matching query
+    fn test_client_list_query_tracking() {
+        let mut client = create_test_chat_client();
+        client.communication_servers.insert(5);
+        client.communication_servers.insert(6);
+
+        client.broadcast(&ChatRequest::ClientListQuery);
+        assert_eq!(client.requests.len(), 2);
+
+        let response = ChatResponse::ClientList {
+            list_of_client_ids: vec![10],
+        };
+        client.handle_msg(serde_json::to_vec(&response).unwrap(), 5, 104);
+        assert_eq!(client.requests.len(), 1);
+    }
+
+    #[test]
+    /// Tests message status tracking through delivery and read receipts
+    fn test_message_status_lifecycle() {
+        let (mut client, _events) = create_test_chat_client_with_events();
+        client.registered_clients.update(2, &[10], Instant::now());
+        client.registrations.insert(2, Registration::Registered);
+
+        let message = Message::new(1, 10, "Tracked".to_string());
+        assert!(!client.handle_command(Box::new(ChatCommand::SendMessage(message))));
+        let id = client.chats_history.get(&10).unwrap()[0].id;
+        assert_eq!(
+            client.chats_history.get(&10).unwrap()[0].status,
+            MessageStatus::Sent
+        );
+        assert_eq!(client.requests.len(), 1);
+
+        client.handle_msg(message_from(10, &ChatPayload::Delivered { id }), 2, 106);
+        assert_eq!(
+            client.chats_history.get(&10).unwrap()[0].status,
+            MessageStatus::Delivered
+        );
+        assert!(client.requests.is_empty());
+
+        client.handle_msg(message_from(10, &ChatPayload::Read { id }), 2, 107);
+        // a late duplicate ack must not move the status back
+        client.handle_msg(message_from(10, &ChatPayload::Delivered { id }), 2, 108);
+        assert_eq!(
+            client.chats_history.get(&10).unwrap()[0].status,
+            MessageStatus::Read
+        );
+    }
+
+    #[test]
+    /// Tests that a correlated message gets its sent and delivered notifications
+    /// under the correlation id
+    fn test_correlated_send_message() {
+        let (mut client, events) = create_test_chat_client_with_events();
+        client.registered_clients.update(2, &[10], Instant::now());
+        client.registrations.insert(2, Registration::Registered);
+
+        let message = Message::new(1, 10, "Tracked".to_string());
+        client.handle_command(Box::new(CorrelatedCommand {
+            correlation_id: 3,
+            command: Box::new(ChatCommand::SendMessage(message)),
+        }));
+        let id = client.chats_history.get(&10).unwrap()[0].id;
+        client.handle_msg(message_from(10, &ChatPayload::Delivered { id }), 2, 109);
+
+        let correlated = events
+            .try_iter()
+            .filter_map(|e| e.into_any().downcast::<CorrelatedEvent>().ok())
+            .filter_map(|e| {
+                let event = e.event.as_any();
+                let sent = matches!(
+                    event.downcast_ref::<ChatEvent>(),
+                    Some(ChatEvent::MessageSent { .. })
+                );
+                let delivered = matches!(
+                    event.downcast_ref::<ChatClientEvent>(),
+                    Some(ChatClientEvent::MessageStatusChanged {
+                        status: MessageStatus::Delivered,
+                        ..
+                    })
+                );
+                (sent || delivered).then_some(e.correlation_id)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(correlated, vec![3, 3]);
+    }
+
+    #[test]
+    /// Tests that a server that stops answering is forgotten and that the
+    /// message in flight through it is sent again through another server
+    fn test_server_failover() {
+        let (client, events) = create_test_chat_client_with_events();
+        let mut client = client.with_max_server_failures(1);
+        for server in [2, 3] {
+            client.communication_servers.insert(server);
+            client
+                .registrations
+                .insert(server, Registration::Registered);
+        }
+        client.registered_clients.update(2, &[10], Instant::now());
+
+        let message = Message::new(1, 10, "Failover".to_string());
+        client.handle_command(Box::new(ChatCommand::SendMessage(message)));
+        client.registered_clients.update(3, &[10], Instant::now());
+        client.requests.set_policy(RetryPolicy {
+            timeout: Duration::ZERO,
+            backoff: 1,
+            max_retries: 0,
+            reroute_after: Duration::from_secs(60),
+        });
+        let _ = client.send_request(&ChatRequest::ClientListQuery, 2);
+        client.handle_command(Box::new(ChatClientCommand::Tick));
+
+        assert!(!client.communication_servers.contains(&2));
+        assert!(client.registered_clients.clients_of(2).is_empty());
+        assert!(
+            client
+                .requests
+                .any(|r, dest| matches!(r, ChatRequest::MessageFor { .. }) && dest == 3)
+        );
+        assert_eq!(
+            client.chats_history.get(&10).unwrap()[0].status,
+            MessageStatus::Sent
+        );
+        let lost = events.try_iter().any(|e| {
+            matches!(
+                e.as_any().downcast_ref::<ChatClientEvent>(),
+                Some(ChatClientEvent::ServerLost { server: 2, .. })
+            )
+        });
+        assert!(lost);
+    }
+
+    #[test]
+    /// Tests that a message timing out fails without counting against its server
+    fn test_message_timeout_keeps_server() {
+        let mut client = create_test_chat_client()
+            .with_max_server_failures(1)
+            .with_retry_policy(RetryPolicy {
+                timeout: Duration::ZERO,
+                backoff: 1,
+                max_retries: 0,
+                reroute_after: Duration::from_secs(60),
+            });
+        client.communication_servers.insert(2);
+        client.registrations.insert(2, Registration::Registered);
+        client.registered_clients.update(2, &[10], Instant::now());
+
+        let message = Message::new(1, 10, "Unanswered".to_string());
+        client.handle_command(Box::new(ChatCommand::SendMessage(message)));
+        client.handle_command(Box::new(ChatClientCommand::Tick));
+        assert!(client.communication_servers.contains(&2));
+        assert_eq!(
+            client.chats_history.get(&10).unwrap()[0].status,
+            MessageStatus::Failed
+        );
+    }
+
+    #[test]
+    /// Tests that queued messages are sent once listed, can be cancelled and expire
+    fn test_outbox() {
+        let (mut client, events) = create_test_chat_client_with_events();
+        client.communication_servers.insert(2);
+        client.registrations.insert(2, Registration::Registered);
+        for text in ["First", "Second", "Third"] {
+            let message = Message::new(1, 10, text.to_string());
+            client.handle_command(Box::new(ChatCommand::SendMessage(message)));
+        }
+        let ids = client
+            .outbox
+            .messages()
+            .iter()
+            .map(|m| m.id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids.len(), 3);
+        client.handle_command(Box::new(ChatClientCommand::CancelQueued(ids[1])));
+        assert_eq!(
+            client.chats_history.get(&10).unwrap()[1].status,
+            MessageStatus::Failed
+        );
+
+        let list = ChatResponse::ClientList {
+            list_of_client_ids: vec![10],
+        };
+        client.handle_msg(serde_json::to_vec(&list).unwrap(), 2, 100);
+        assert!(client.outbox.is_empty());
+        let statuses = client.chats_history.get(&10).unwrap();
+        assert_eq!(statuses[0].status, MessageStatus::Sent);
+        assert_eq!(statuses[2].status, MessageStatus::Sent);
+
+        client = client.with_outbox_config(OutboxConfig {
+            max_age: Duration::ZERO,
+            max_size: 8,
+        });
+        let message = Message::new(1, 11, "Late".to_string());
+        client.handle_command(Box::new(ChatCommand::SendMessage(message)));
+        client.handle_command(Box::new(ChatClientCommand::Tick));
+        assert!(client.outbox.is_empty());
+        assert_eq!(
+            client.chats_history.get(&11).unwrap()[0].status,
+            MessageStatus::Failed
+        );
+        let expired = events.try_iter().any(|e| {
+            matches!(
+                e.as_any().downcast_ref::<ChatClientEvent>(),
+                Some(ChatClientEvent::MessageExpired { to: 11, .. })
+            )
+        });
+        assert!(expired);
+    }
+
+    #[test]
+    /// Tests that messages failed over to the outbox stay ahead of the ones
+    /// queued after them and leave once a server listing their recipient
+    /// registers the client
+    fn test_outbox_requeue() {
+        let (mut client, _events) = create_test_chat_client_with_events();
+        client.communication_servers.insert(2);
+        client.registrations.insert(2, Registration::Registered);
+        client.registered_clients.update(2, &[10], Instant::now());
+        for text in ["First", "Second"] {
+            let message = Message::new(1, 10, text.to_string());
+            client.handle_command(Box::new(ChatCommand::SendMessage(message)));
+        }
+        client.registered_clients.remove_server(2);
+        let message = Message::new(1, 10, "Third".to_string());
+        client.handle_command(Box::new(ChatCommand::SendMessage(message)));
+        let _ = client.drop_server(2);
+        let queued = client
+            .outbox
+            .messages()
+            .iter()
+            .map(|m| m.id)
+            .collect::<Vec<_>>();
+        let sent = client.chats_history.get(&10).unwrap();
+        assert_eq!(queued, sent.iter().map(|e| e.id).collect::<Vec<_>>());
+
+        client.communication_servers.insert(3);
+        let list = ChatResponse::ClientList {
+            list_of_client_ids: vec![10],
+        };
+        client.handle_msg(serde_json::to_vec(&list).unwrap(), 3, 101);
+        assert_eq!(client.outbox.len(), 3);
+        let registered = ChatResponse::RegistrationSuccess;
+        client.handle_msg(serde_json::to_vec(&registered).unwrap(), 3, 102);
+        assert!(client.outbox.is_empty());
+    }
+
+    #[test]
+    /// Tests that a message to a client listed only by a server the client is not
+    /// registered with waits in the outbox until it registers there
+    fn test_outbox_unregistered_server() {
+        let (mut client, _events) = create_test_chat_client_with_events();
+        client.communication_servers.insert(3);
+        client.registered_clients.update(3, &[10], Instant::now());
+        let message = Message::new(1, 10, "Waiting".to_string());
+        client.handle_command(Box::new(ChatCommand::SendMessage(message)));
+        assert_eq!(client.outbox.len(), 1);
+        assert!(client.requests.is_empty());
+        assert_eq!(
+            client.chats_history.get(&10).unwrap()[0].status,
+            MessageStatus::Queued
+        );
+
+        let registered = ChatResponse::RegistrationSuccess;
+        client.handle_msg(serde_json::to_vec(&registered).unwrap(), 3, 102);
+        assert!(client.outbox.is_empty());
+        assert_eq!(
+            client.chats_history.get(&10).unwrap()[0].status,
+            MessageStatus::Sent
+        );
+    }
+
+    #[test]
+    /// Tests that only the messages actually sent are numbered, the numbering
+    /// surviving a restart
+    fn test_send_numbering() {
+        let path = std::env::temp_dir().join("client-chat-send-numbering.jsonl");
+        let _ = std::fs::remove_file(&path);
+        let store = FileHistoryStore::open(&path).unwrap();
+        let (client, _events) = create_test_chat_client_with_events();
+        let mut client = client.with_history_store(Box::new(store)).unwrap();
+        client.communication_servers.insert(2);
+        client.registrations.insert(2, Registration::Registered);
+
+        let message = Message::new(1, 10, "Cancelled".to_string());
+        client.handle_command(Box::new(ChatCommand::SendMessage(message)));
+        let cancelled = client.outbox.messages()[0].id;
+        client.handle_command(Box::new(ChatClientCommand::CancelQueued(cancelled)));
+        client.registered_clients.update(2, &[10], Instant::now());
+        let message = Message::new(1, 10, "Sent".to_string());
+        client.handle_command(Box::new(ChatCommand::SendMessage(message)));
+        let sent_with = |client: &ChatClient, n: u64| {
+            client.requests.any(|r, _| match r {
+                ChatRequest::MessageFor { message, .. } => matches!(
+                    ChatPayload::decode(message),
+                    Some(ChatPayload::Text { seq: Some(seq), .. }) if seq == n
+                ),
+                _ => false,
+            })
+        };
+        assert!(sent_with(&client, 0));
+        assert!(!sent_with(&client, 1));
+
+        let store = FileHistoryStore::open(&path).unwrap();
+        let (client, _events) = create_test_chat_client_with_events();
+        let mut client = client.with_history_store(Box::new(store)).unwrap();
+        client.communication_servers.insert(2);
+        client.registrations.insert(2, Registration::Registered);
+        client.registered_clients.update(2, &[10], Instant::now());
+        let message = Message::new(1, 10, "After restart".to_string());
+        client.handle_command(Box::new(ChatCommand::SendMessage(message)));
+        assert!(sent_with(&client, 1));
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    /// Tests that the next message expected from a peer survives a restart and
+    /// that a sender numbering from zero again is followed anew
+    fn test_receive_numbering() {
+        let path = std::env::temp_dir().join("client-chat-receive-numbering.jsonl");
+        let _ = std::fs::remove_file(&path);
+        let text = |n: u128, seq: Option<u64>| ChatPayload::Text {
+            id: Uuid::from_u128(n),
+            text: format!("Message {n}"),
+            seq,
+        };
+        let store = FileHistoryStore::open(&path).unwrap();
+        let mut client = create_test_chat_client()
+            .with_history_store(Box::new(store))
+            .unwrap();
+        for (n, seq) in [(0, Some(0)), (1, Some(1)), (9, None)] {
+            client.handle_msg(message_from(20, &text(n, seq)), 5, 120);
+        }
+
+        let store = FileHistoryStore::open(&path).unwrap();
+        let mut client = create_test_chat_client()
+            .with_history_store(Box::new(store))
+            .unwrap();
+        for (n, seq) in [(3, Some(3)), (2, Some(2))] {
+            client.handle_msg(message_from(20, &text(n, seq)), 5, 121);
+        }
+        let texts = |client: &ChatClient| {
+            client.chats_history[&20]
+                .iter()
+                .map(|e| e.message.text.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(texts(&client)[3..], ["Message 2", "Message 3"]);
+
+        // the sender lost its counter
+        for (n, seq) in [(10, Some(0)), (11, Some(1))] {
+            client.handle_msg(message_from(20, &text(n, seq)), 5, 122);
+        }
+        assert_eq!(texts(&client)[5..], ["Message 10", "Message 11"]);
+        assert_eq!(client.reorder.next(20), Some(2));
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    /// Tests that a retransmitted message is stored once
+    fn test_retransmitted_message_stored_once() {
+        let mut client = create_test_chat_client();
+        let payload = ChatPayload::Text {
+            id: Uuid::from_u128(7),
+            text: "Once".to_string(),
+            seq: Some(0),
+        };
+
+        client.handle_msg(message_from(20, &payload), 5, 109);
+        client.handle_msg(message_from(20, &payload), 5, 110);
+        assert_eq!(client.chats_history.get(&20).unwrap().len(), 1);
+    }
+
+    #[test]
+    /// Tests that reordered and duplicated messages are stored in the order sent
+    fn test_reordered_messages() {
+        let mut client = create_test_chat_client();
+        let text = |n: u64| ChatPayload::Text {
+            id: Uuid::from_u128(u128::from(n) + 1),
+            text: format!("Message {n}"),
+            seq: Some(n),
+        };
+
+        for seq in [1, 0, 1, 2, 0] {
+            client.handle_msg(message_from(20, &text(seq)), 5, 120);
+        }
+        let texts = client.chats_history[&20]
+            .iter()
+            .map(|e| e.message.text.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(texts, vec!["Message 0", "Message 1", "Message 2"]);
+
+        // a missing message is not waited on past the configured time
+        client = client.with_reorder_config(ReorderConfig {
+            max_wait: Duration::ZERO,
+            max_held: 8,
+        });
+        client.handle_msg(message_from(20, &text(4)), 5, 121);
+        assert_eq!(client.chats_history[&20].len(), 4);
+        assert_eq!(client.chats_history[&20][3].message.text, "Message 4");
+    }
+
+    #[test]
+    /// Tests group creation and fan-out of group messages to every member
+    fn test_group_fan_out() {
+        let (mut client, events) = create_test_chat_client_with_events();
+        client.registered_clients.update(2, &[10], Instant::now());
+        client.registered_clients.update(3, &[11], Instant::now());
+        client.registrations.insert(2, Registration::Registered);
+        client.registrations.insert(3, Registration::Registered);
+
+        let cmd = ChatClientCommand::CreateGroup {
+            name: "Friends".to_string(),
+            members: vec![10, 11],
+        };
+        assert!(!client.handle_command(Box::new(cmd)));
+        let group_id = *client.groups.keys().next().unwrap();
+        assert_eq!(client.groups[&group_id].members, vec![1, 10, 11]);
+
+        let cmd = ChatClientCommand::SendGroupMessage {
+            group_id,
+            text: "Hi all".to_string(),
+        };
+        assert!(!client.handle_command(Box::new(cmd)));
+        let id = client.group_history[&group_id][0].id;
+        // one tracked copy per member
+        let copies = client.requests.resolve_by(|r, _| payload_id(r) == Some(id));
+        assert_eq!(copies.len(), 2);
+
+        let delivered = |events: &Receiver<Box<dyn Event>>| {
+            events.try_iter().any(|e| {
+                matches!(
+                    e.as_any().downcast_ref::<ChatClientEvent>(),
+                    Some(ChatClientEvent::MessageStatusChanged {
+                        id: changed,
+                        status: MessageStatus::Delivered,
+                        ..
+                    }) if *changed == id
+                )
+            })
+        };
+        client.handle_msg(message_from(10, &ChatPayload::Delivered { id }), 2, 111);
+        assert_eq!(
+            client.group_history[&group_id][0].status,
+            MessageStatus::Sent
+        );
+        assert!(!delivered(&events));
+        client.handle_msg(message_from(11, &ChatPayload::Delivered { id }), 3, 112);
+        assert_eq!(
+            client.group_history[&group_id][0].status,
+            MessageStatus::Delivered
+        );
+        assert!(delivered(&events));
+
+        // acknowledged invites are no longer retransmitted
+        for (member, server) in [(10, 2), (11, 3)] {
+            let ack = ChatPayload::Delivered { id: group_id };
+            client.handle_msg(message_from(member, &ack), server, 113);
+        }
+        assert!(client.requests.is_empty());
+    }
+
+    #[test]
+    /// Tests that a group message whose copy timed out fails as a whole
+    fn test_group_message_timeout() {
+        let (mut client, events) = create_test_chat_client_with_events();
+        client = client.with_retry_policy(RetryPolicy {
+            timeout: Duration::ZERO,
+            backoff: 1,
+            max_retries: 0,
+            reroute_after: Duration::from_secs(60),
+        });
+        client.registered_clients.update(2, &[10], Instant::now());
+        client.registrations.insert(2, Registration::Registered);
+        let group_id = Uuid::from_u128(9);
+        client.groups.insert(
+            group_id,
+            Group::new(group_id, "Friends".to_string(), vec![1, 10]),
+        );
+
+        let cmd = ChatClientCommand::SendGroupMessage {
+            group_id,
+            text: "Hi all".to_string(),
+        };
+        client.handle_command(Box::new(cmd));
+        client.handle_command(Box::new(ChatClientCommand::Tick));
+        assert_eq!(
+            client.group_history[&group_id][0].status,
+            MessageStatus::Failed
+        );
+        assert!(client.group_acks.is_empty());
+        let id = client.group_history[&group_id][0].id;
+        let failed = events.try_iter().any(|e| {
+            matches!(
+                e.as_any().downcast_ref::<ChatClientEvent>(),
+                Some(ChatClientEvent::MessageStatusChanged {
+                    peer: 10,
+                    id: changed,
+                    status: MessageStatus::Failed,
+                    ..
+                }) if *changed == id
+            )
+        });
+        assert!(failed);
+    }
+
+    #[test]
+    /// Tests that received group messages are kept in the group history and that
+    /// repeated invites are notified once
+    fn test_group_message_reception() {
+        let (mut client, events) = create_test_chat_client_with_events();
+        let group_id = Uuid::from_u128(9);
+        let invite = ChatPayload::GroupInvite {
+            group_id,
+            name: "Friends".to_string(),
+            members: vec![1, 10, 11],
+        };
+        client.handle_msg(message_from(10, &invite), 2, 113);
+        client.handle_msg(message_from(10, &invite), 2, 113);
+        let text = ChatPayload::GroupText {
+            id: Uuid::from_u128(10),
+            group_id,
+            text: "Hello group".to_string(),
+        };
+        client.handle_msg(message_from(10, &text), 2, 114);
+
+        assert_eq!(client.groups[&group_id].name, "Friends".to_string());
+        assert_eq!(client.group_history[&group_id].len(), 1);
+        assert!(!client.chats_history.contains_key(&10));
+        // the retransmitted invite changes nothing and is not notified again
+        let joined = events
+            .try_iter()
+            .filter(|e| {
+                matches!(
+                    e.as_any().downcast_ref::<ChatClientEvent>(),
+                    Some(ChatClientEvent::GroupJoined { .. })
+                )
+            })
+            .count();
+        assert_eq!(joined, 1);
+    }
+
+    #[test]
+    /// Tests that a registration from a node that is not a known chat server is
+    /// reported and ignored
+    fn test_registration_from_unknown_sender() {
+        let (mut client, events) = create_test_chat_client_with_events();
+
+        let serialized = serde_json::to_vec(&ChatResponse::RegistrationSuccess).unwrap();
+        client.handle_msg(serialized, 5, 115);
+        assert!(!client.is_registered(5));
+        let reported = events.try_iter().any(|e| {
+            matches!(
+                e.as_any().downcast_ref::<ClientErrorEvent>(),
+                Some(ClientErrorEvent {
+                    error: ClientError::UnknownSender(5),
+                    ..
+                })
+            )
+        });
+        assert!(reported);
+    }
+
+    #[test]
+    /// Tests that messages are only routed through servers we are registered with
+    fn test_destination_requires_registration() {
+        let mut client = create_test_chat_client();
+        client.registered_clients.update(2, &[10], Instant::now());
+        client.registered_clients.update(3, &[10], Instant::now());
+
+        assert_eq!(client.find_destination_by_client_id(10), None);
+
+        client.registrations.insert(3, Registration::Registered);
+        assert_eq!(client.find_destination_by_client_id(10), Some(3));
     }
 }
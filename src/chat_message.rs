@@ -0,0 +1,93 @@
+use common::types::Message;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use wg_internal::network::NodeId;
+
+/// Lifecycle of a message in the chat history.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MessageStatus {
+    /// Waiting for a route to the recipient.
+    Queued,
+    /// Handed to the network, not acknowledged yet.
+    Sent,
+    /// Acknowledged by the recipient.
+    Delivered,
+    /// Read by the recipient.
+    Read,
+    Failed,
+}
+
+/// A message in the history, with the id used to acknowledge it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatEntry {
+    pub id: Uuid,
+    pub message: Message,
+    pub status: MessageStatus,
+}
+
+impl ChatEntry {
+    #[must_use]
+    pub fn new(id: Uuid, message: Message, status: MessageStatus) -> Self {
+        Self {
+            id,
+            message,
+            status,
+        }
+    }
+}
+
+/// Client-to-client protocol carried in the text of `ChatRequest::MessageFor`,
+/// which chat servers relay untouched.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChatPayload {
+    Text {
+        id: Uuid,
+        text: String,
+        /// Position among the messages sent to the recipient, absent from older
+        /// clients whose messages are taken in arrival order.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        seq: Option<u64>,
+    },
+    Delivered {
+        id: Uuid,
+    },
+    Read {
+        id: Uuid,
+    },
+    /// Full member list of a group, sent to every member on creation and invites.
+    GroupInvite {
+        group_id: Uuid,
+        name: String,
+        members: Vec<NodeId>,
+    },
+    GroupText {
+        id: Uuid,
+        group_id: Uuid,
+        text: String,
+    },
+}
+
+impl ChatPayload {
+    #[must_use]
+    pub fn encode(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    /// Parses a relayed message, `None` for plain text sent by other clients.
+    #[must_use]
+    pub fn decode(message: &str) -> Option<Self> {
+        serde_json::from_str(message).ok()
+    }
+
+    /// Id of the message, or of the group for invites.
+    #[must_use]
+    pub fn id(&self) -> Uuid {
+        match self {
+            ChatPayload::Text { id, .. }
+            | ChatPayload::Delivered { id }
+            | ChatPayload::Read { id }
+            | ChatPayload::GroupText { id, .. } => *id,
+            ChatPayload::GroupInvite { group_id, .. } => *group_id,
+        }
+    }
+}
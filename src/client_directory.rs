@@ -0,0 +1,220 @@
+use std::collections::{BTreeSet, HashMap};
+use std::time::{Duration, Instant};
+use wg_internal::network::NodeId;
+
+#[derive(Debug, Clone, Copy)]
+pub struct DirectoryConfig {
+    /// Time after which a client no list confirmed anymore is dropped.
+    pub ttl: Duration,
+    /// How long a client list is used before it is queried again.
+    pub refresh_every: Duration,
+}
+
+impl Default for DirectoryConfig {
+    fn default() -> Self {
+        Self {
+            ttl: Duration::from_secs(90),
+            refresh_every: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Clients a server started and stopped listing between two of its lists.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DirectoryChange {
+    pub joined: Vec<NodeId>,
+    pub left: Vec<NodeId>,
+}
+
+#[derive(Debug, Default)]
+struct ServerClients {
+    /// Client, when a list of the server last included it.
+    clients: HashMap<NodeId, Instant>,
+    updated: Option<Instant>,
+    requested: Option<Instant>,
+}
+
+/// Clients registered to each chat server, as last confirmed by its lists.
+#[derive(Debug, Default)]
+pub struct ClientDirectory {
+    config: DirectoryConfig,
+    servers: HashMap<NodeId, ServerClients>,
+}
+
+impl ClientDirectory {
+    #[must_use]
+    pub fn new(config: DirectoryConfig) -> Self {
+        Self {
+            config,
+            servers: HashMap::new(),
+        }
+    }
+
+    pub fn set_config(&mut self, config: DirectoryConfig) {
+        self.config = config;
+    }
+
+    /// Replaces the clients of `server` with the ones of its latest list.
+    pub fn update(&mut self, server: NodeId, clients: &[NodeId], now: Instant) -> DirectoryChange {
+        let entry = self.servers.entry(server).or_default();
+        let mut change = DirectoryChange {
+            joined: clients
+                .iter()
+                .filter(|c| !entry.clients.contains_key(c))
+                .copied()
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect(),
+            left: entry
+                .clients
+                .keys()
+                .filter(|c| !clients.contains(c))
+                .copied()
+                .collect(),
+        };
+        change.left.sort_unstable();
+        entry.clients = clients.iter().map(|c| (*c, now)).collect();
+        entry.updated = Some(now);
+        entry.requested = None;
+        change
+    }
+
+    /// Forgets a server, returning the clients it listed.
+    pub fn remove_server(&mut self, server: NodeId) -> Vec<NodeId> {
+        let mut left = self
+            .servers
+            .remove(&server)
+            .map(|s| s.clients.into_keys().collect::<Vec<_>>())
+            .unwrap_or_default();
+        left.sort_unstable();
+        left
+    }
+
+    /// Drops the clients not confirmed within the time to live, returning them
+    /// with their server.
+    pub fn expire(&mut self, now: Instant) -> Vec<(NodeId, NodeId)> {
+        let ttl = self.config.ttl;
+        let mut expired = vec![];
+        for (server, entry) in &mut self.servers {
+            entry.clients.retain(|client, confirmed| {
+                let alive = now.duration_since(*confirmed) < ttl;
+                if !alive {
+                    expired.push((*server, *client));
+                }
+                alive
+            });
+        }
+        expired.sort_unstable();
+        expired
+    }
+
+    /// Servers whose list is due for a refresh and not already being queried,
+    /// marking them as queried.
+    pub fn due_for_refresh(&mut self, now: Instant) -> Vec<NodeId> {
+        let refresh_every = self.config.refresh_every;
+        let mut due = self
+            .servers
+            .iter_mut()
+            .filter(|(_, s)| {
+                let last = s.requested.or(s.updated);
+                last.is_some_and(|t| now.duration_since(t) >= refresh_every)
+            })
+            .map(|(id, s)| {
+                s.requested = Some(now);
+                *id
+            })
+            .collect::<Vec<_>>();
+        due.sort_unstable();
+        due
+    }
+
+    /// Whether every list is recent enough to be answered without querying it.
+    #[must_use]
+    pub fn is_fresh(&self, now: Instant) -> bool {
+        self.servers.values().all(|s| {
+            s.updated
+                .is_some_and(|t| now.duration_since(t) < self.config.refresh_every)
+        })
+    }
+
+    /// Servers listing `client`, lowest id first.
+    #[must_use]
+    pub fn servers_of(&self, client: NodeId) -> Vec<NodeId> {
+        let mut servers = self
+            .servers
+            .iter()
+            .filter(|(_, s)| s.clients.contains_key(&client))
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        servers.sort_unstable();
+        servers
+    }
+
+    #[must_use]
+    pub fn clients_of(&self, server: NodeId) -> Vec<NodeId> {
+        let mut clients = self
+            .servers
+            .get(&server)
+            .map(|s| s.clients.keys().copied().collect::<Vec<_>>())
+            .unwrap_or_default();
+        clients.sort_unstable();
+        clients
+    }
+
+    #[must_use]
+    pub fn contains(&self, client: NodeId) -> bool {
+        self.servers
+            .values()
+            .any(|s| s.clients.contains_key(&client))
+    }
+
+    /// Every listed client once, lowest id first.
+    #[must_use]
+    pub fn clients(&self) -> Vec<NodeId> {
+        self.servers
+            .values()
+            .flat_map(|s| s.clients.keys().copied())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.servers.values().all(|s| s.clients.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod client_directory_tests {
+    use super::*;
+
+    #[test]
+    /// Tests join and leave detection, expiry and refresh scheduling
+    fn test_update_and_expiry() {
+        let mut directory = ClientDirectory::new(DirectoryConfig {
+            ttl: Duration::from_secs(20),
+            refresh_every: Duration::from_secs(10),
+        });
+        let start = Instant::now();
+        directory.update(2, &[10, 11], start);
+        directory.update(3, &[11], start);
+        let change = directory.update(2, &[11, 12], start);
+        assert_eq!(change.joined, vec![12]);
+        assert_eq!(change.left, vec![10]);
+        assert_eq!(directory.servers_of(11), vec![2, 3]);
+        assert!(directory.is_fresh(start));
+
+        let later = start + Duration::from_secs(10);
+        assert!(!directory.is_fresh(later));
+        assert_eq!(directory.due_for_refresh(later), vec![2, 3]);
+        assert!(directory.due_for_refresh(later).is_empty());
+        directory.update(3, &[11], later);
+
+        let expired = start + Duration::from_secs(20);
+        assert_eq!(directory.expire(expired), vec![(2, 11), (2, 12)]);
+        assert_eq!(directory.clients(), vec![11]);
+        assert_eq!(directory.remove_server(3), vec![11]);
+        assert!(directory.is_empty());
+    }
+}
@@ -0,0 +1,180 @@
+use common::types::{MediaFile, TextFile};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+const EXTENSION: &str = "json";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredFile {
+    text_file: TextFile,
+    media: Vec<MediaFile>,
+}
+
+/// Cached files persisted in a directory, one `<text file uuid>.json` per entry.
+#[derive(Debug)]
+pub struct DiskCache {
+    dir: PathBuf,
+}
+
+impl DiskCache {
+    pub fn open(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    fn path(&self, id: Uuid) -> PathBuf {
+        self.dir.join(format!("{id}.{EXTENSION}"))
+    }
+
+    /// Writes an entry, replacing the previous version atomically.
+    pub fn store(&self, text_file: &TextFile, media: &[MediaFile]) -> io::Result<()> {
+        let stored = StoredFile {
+            text_file: text_file.clone(),
+            media: media.to_vec(),
+        };
+        let data = serde_json::to_vec(&stored).map_err(io::Error::other)?;
+        let path = self.path(text_file.id);
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, data)?;
+        fs::rename(tmp, path)
+    }
+
+    pub fn remove(&self, id: Uuid) -> io::Result<()> {
+        match fs::remove_file(self.path(id)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    // the directory may be shared, so only `<uuid>.json` entries and their
+    // `<uuid>.tmp` leftovers belong to the cache
+    fn owns(path: &Path) -> bool {
+        path.is_file()
+            && matches!(
+                path.extension().and_then(|e| e.to_str()),
+                Some(EXTENSION | "tmp")
+            )
+            && path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .is_some_and(|s| Uuid::parse_str(s).is_ok())
+    }
+
+    pub fn clear(&self) -> io::Result<()> {
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if Self::owns(&path) {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads every valid entry, deleting the ones that fail validation and the
+    /// leftovers of interrupted writes. Other files are left alone.
+    pub fn load(&self) -> io::Result<Vec<(TextFile, Vec<MediaFile>)>> {
+        let mut files = vec![];
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if !Self::owns(&path) {
+                continue;
+            }
+            if let Some(stored) = Self::read_valid(&path) {
+                files.push((stored.text_file, stored.media));
+            } else {
+                fs::remove_file(&path)?;
+            }
+        }
+        Ok(files)
+    }
+
+    // an entry is valid when it is named after its text file and only holds
+    // distinct media referenced by it
+    fn read_valid(path: &Path) -> Option<StoredFile> {
+        if path.extension().and_then(|e| e.to_str()) != Some(EXTENSION) {
+            return None;
+        }
+        let id = Uuid::parse_str(path.file_stem()?.to_str()?).ok()?;
+        let stored = serde_json::from_slice::<StoredFile>(&fs::read(path).ok()?).ok()?;
+        if stored.text_file.id != id {
+            return None;
+        }
+        let refs = stored.text_file.get_media_ids();
+        let mut seen = HashSet::new();
+        if stored
+            .media
+            .iter()
+            .all(|m| refs.contains(&m.id) && seen.insert(m.id))
+        {
+            Some(stored)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod disk_cache_tests {
+    use super::*;
+    use common::types::MediaReference;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("client-disk-cache-{name}"));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    /// Tests that stored entries are loaded back
+    fn test_store_and_load() {
+        let dir = temp_dir("roundtrip");
+        let cache = DiskCache::open(&dir).unwrap();
+        let media_ref = MediaReference::new(6);
+        let text_file = TextFile::new(
+            "Article".to_string(),
+            "Content".to_string(),
+            vec![media_ref.clone()],
+        );
+        let media = MediaFile {
+            id: media_ref.id,
+            title: "Image".to_string(),
+            content: vec![vec![1, 2, 3]],
+        };
+        cache
+            .store(&text_file, std::slice::from_ref(&media))
+            .unwrap();
+
+        let loaded = DiskCache::open(&dir).unwrap().load().unwrap();
+        assert_eq!(loaded, vec![(text_file.clone(), vec![media])]);
+
+        cache.remove(text_file.id).unwrap();
+        assert!(cache.load().unwrap().is_empty());
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    /// Tests that corrupted or mismatching entries are discarded on load, while
+    /// files the cache does not own survive loads and clears
+    fn test_invalid_entries_discarded() {
+        let dir = temp_dir("invalid");
+        let cache = DiskCache::open(&dir).unwrap();
+        let text_file = TextFile::new("Article".to_string(), "Content".to_string(), vec![]);
+        cache.store(&text_file, &[]).unwrap();
+        fs::rename(cache.path(text_file.id), cache.path(Uuid::nil())).unwrap();
+        fs::write(cache.path(Uuid::from_u128(1)), b"not json").unwrap();
+        fs::write(dir.join("bookmarks.json"), b"{}").unwrap();
+
+        assert!(cache.load().unwrap().is_empty());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        cache.store(&text_file, &[]).unwrap();
+        cache.clear().unwrap();
+        assert!(dir.join("bookmarks.json").is_file());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        let _ = fs::remove_dir_all(dir);
+    }
+}
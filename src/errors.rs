@@ -1,4 +1,5 @@
 use common::network::NetworkError;
+use wg_internal::network::NodeId;
 
 
 #[derive(Debug)]
@@ -13,6 +14,15 @@ pub enum ClientError {
     NoLocationError,
     SerializationError,
     UuidParseError,
+    IoError(std::io::Error),
+    Disconnected,
+    FileNotFound(uuid::Uuid),
+    DeliveryFailed,
+    RoutingError { destination: NodeId, error: NetworkError },
+    DeserializationError { from: NodeId, reason: String },
+    UnknownSender(NodeId),
+    ChecksumMismatch { media_id: uuid::Uuid, from: NodeId },
+    NoSuchLink(usize),
 }
 
 impl std::fmt::Display for ClientError {
@@ -28,6 +38,21 @@ impl std::fmt::Display for ClientError {
             ClientError::NoLocationError => write!(f, "No location information available"),
             ClientError::UuidParseError => write!(f, "Failed to parse UUID"),
             ClientError::SerializationError => write!(f, "Serialization error"),
+            ClientError::IoError(e) => write!(f, "I/O error: {e}"),
+            ClientError::Disconnected => write!(f, "Client disconnected"),
+            ClientError::FileNotFound(id) => write!(f, "File {id} not found"),
+            ClientError::DeliveryFailed => write!(f, "Message delivery failed"),
+            ClientError::RoutingError { destination, error } => {
+                write!(f, "Routing error towards {destination}: {error}")
+            }
+            ClientError::DeserializationError { from, reason } => {
+                write!(f, "Malformed message from {from}: {reason}")
+            }
+            ClientError::UnknownSender(id) => write!(f, "Unexpected message from {id}"),
+            ClientError::ChecksumMismatch { media_id, from } => {
+                write!(f, "Media {media_id} from {from} failed its checksum")
+            }
+            ClientError::NoSuchLink(index) => write!(f, "No link {index} on the current page"),
         }
     }
 }
@@ -40,3 +65,9 @@ impl From<NetworkError> for ClientError {
         ClientError::NetworkError(value)
     }
 }
+
+impl From<std::io::Error> for ClientError {
+    fn from(value: std::io::Error) -> Self {
+        ClientError::IoError(value)
+    }
+}
@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use uuid::Uuid;
+use wg_internal::network::NodeId;
+
+/// A named conversation between several clients.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Group {
+    pub id: Uuid,
+    pub name: String,
+    /// Every member, the creator included.
+    pub members: Vec<NodeId>,
+}
+
+impl Group {
+    #[must_use]
+    pub fn new(id: Uuid, name: String, members: Vec<NodeId>) -> Self {
+        let mut group = Self {
+            id,
+            name,
+            members: vec![],
+        };
+        group.add_members(&members);
+        group
+    }
+
+    /// Adds the members not already in the group, returning the new ones.
+    pub fn add_members(&mut self, members: &[NodeId]) -> Vec<NodeId> {
+        let mut added = vec![];
+        for m in members {
+            if !self.members.contains(m) {
+                self.members.push(*m);
+                added.push(*m);
+            }
+        }
+        added
+    }
+
+    /// Members a message from `sender` has to be fanned out to.
+    #[must_use]
+    pub fn recipients(&self, sender: NodeId) -> HashSet<NodeId> {
+        self.members
+            .iter()
+            .copied()
+            .filter(|m| *m != sender)
+            .collect()
+    }
+}
@@ -0,0 +1,301 @@
+use crate::chat_message::{ChatEntry, MessageStatus};
+use crate::errors::ClientError;
+use common::types::Message;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use uuid::Uuid;
+use wg_internal::network::NodeId;
+
+pub type History = HashMap<NodeId, Vec<ChatEntry>>;
+
+fn set_status_in(history: &mut History, key: NodeId, id: Uuid, status: MessageStatus) {
+    if let Some(entry) = history
+        .get_mut(&key)
+        .and_then(|chat| chat.iter_mut().find(|e| e.id == id))
+    {
+        entry.status = status;
+    }
+}
+
+/// Where `ChatClient` keeps its conversations.
+pub trait HistoryStore: Send {
+    /// Records `entry` in the conversation with `key`.
+    fn append(&mut self, key: NodeId, entry: &ChatEntry) -> Result<(), ClientError>;
+    /// Records a status change of the message `id` in the conversation with `key`.
+    fn set_status(
+        &mut self,
+        key: NodeId,
+        id: Uuid,
+        status: MessageStatus,
+    ) -> Result<(), ClientError>;
+    /// Records that `count` messages were sent to `key` so far.
+    fn set_sent(&mut self, key: NodeId, count: u64) -> Result<(), ClientError>;
+    /// Records the sequence number of the next message expected from `key`.
+    fn set_received(&mut self, key: NodeId, next: u64) -> Result<(), ClientError>;
+    /// Returns every stored conversation.
+    fn load(&mut self) -> Result<History, ClientError>;
+    /// Returns the number of messages sent to each peer.
+    fn load_sent(&mut self) -> Result<HashMap<NodeId, u64>, ClientError>;
+    /// Returns the sequence number of the next message expected from each peer.
+    fn load_received(&mut self) -> Result<HashMap<NodeId, u64>, ClientError>;
+    /// Makes sure everything appended so far is durable.
+    fn flush(&mut self) -> Result<(), ClientError>;
+}
+
+/// Keeps history only for the lifetime of the process.
+#[derive(Debug, Default)]
+pub struct MemoryHistoryStore {
+    history: History,
+    sent: HashMap<NodeId, u64>,
+    received: HashMap<NodeId, u64>,
+}
+
+impl HistoryStore for MemoryHistoryStore {
+    fn append(&mut self, key: NodeId, entry: &ChatEntry) -> Result<(), ClientError> {
+        self.history.entry(key).or_default().push(entry.clone());
+        Ok(())
+    }
+
+    fn set_status(
+        &mut self,
+        key: NodeId,
+        id: Uuid,
+        status: MessageStatus,
+    ) -> Result<(), ClientError> {
+        set_status_in(&mut self.history, key, id, status);
+        Ok(())
+    }
+
+    fn set_sent(&mut self, key: NodeId, count: u64) -> Result<(), ClientError> {
+        self.sent.insert(key, count);
+        Ok(())
+    }
+
+    fn set_received(&mut self, key: NodeId, next: u64) -> Result<(), ClientError> {
+        self.received.insert(key, next);
+        Ok(())
+    }
+
+    fn load(&mut self) -> Result<History, ClientError> {
+        Ok(self.history.clone())
+    }
+
+    fn load_sent(&mut self) -> Result<HashMap<NodeId, u64>, ClientError> {
+        Ok(self.sent.clone())
+    }
+
+    fn load_received(&mut self) -> Result<HashMap<NodeId, u64>, ClientError> {
+        Ok(self.received.clone())
+    }
+
+    fn flush(&mut self) -> Result<(), ClientError> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum Record {
+    Message {
+        key: NodeId,
+        id: Uuid,
+        from: NodeId,
+        to: NodeId,
+        text: String,
+        status: MessageStatus,
+    },
+    Status {
+        key: NodeId,
+        id: Uuid,
+        status: MessageStatus,
+    },
+    Sent {
+        key: NodeId,
+        count: u64,
+    },
+    Received {
+        key: NodeId,
+        next: u64,
+    },
+}
+
+/// Append-only log of messages and status changes, one JSON record per line.
+#[derive(Debug)]
+pub struct FileHistoryStore {
+    path: PathBuf,
+    writer: BufWriter<File>,
+}
+
+impl FileHistoryStore {
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, ClientError> {
+        let path = path.into();
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .read(true)
+            .open(&path)?;
+        // terminate a record cut short by a crash so the next one starts on its own line
+        if file.metadata()?.len() > 0 {
+            let mut last = [0u8];
+            file.seek(SeekFrom::End(-1))?;
+            file.read_exact(&mut last)?;
+            if last[0] != b'\n' {
+                file.write_all(b"\n")?;
+            }
+        }
+        Ok(Self {
+            path,
+            writer: BufWriter::new(file),
+        })
+    }
+
+    fn write(&mut self, record: &Record) -> Result<(), ClientError> {
+        serde_json::to_writer(&mut self.writer, record)
+            .map_err(|_| ClientError::SerializationError)?;
+        self.writer.write_all(b"\n")?;
+        // hand every record to the OS so a crash loses at most the current one
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    // a record cut short by a crash is skipped instead of failing the whole load
+    fn records(&self) -> Result<Vec<Record>, ClientError> {
+        let mut records = vec![];
+        for line in BufReader::new(File::open(&self.path)?).lines() {
+            if let Ok(record) = serde_json::from_str(&line?) {
+                records.push(record);
+            }
+        }
+        Ok(records)
+    }
+}
+
+impl HistoryStore for FileHistoryStore {
+    fn append(&mut self, key: NodeId, entry: &ChatEntry) -> Result<(), ClientError> {
+        self.write(&Record::Message {
+            key,
+            id: entry.id,
+            from: entry.message.from,
+            to: entry.message.to,
+            text: entry.message.text.clone(),
+            status: entry.status,
+        })
+    }
+
+    fn set_status(
+        &mut self,
+        key: NodeId,
+        id: Uuid,
+        status: MessageStatus,
+    ) -> Result<(), ClientError> {
+        self.write(&Record::Status { key, id, status })
+    }
+
+    fn set_sent(&mut self, key: NodeId, count: u64) -> Result<(), ClientError> {
+        self.write(&Record::Sent { key, count })
+    }
+
+    fn set_received(&mut self, key: NodeId, next: u64) -> Result<(), ClientError> {
+        self.write(&Record::Received { key, next })
+    }
+
+    fn load(&mut self) -> Result<History, ClientError> {
+        let mut history = History::new();
+        for record in self.records()? {
+            match record {
+                Record::Message {
+                    key,
+                    id,
+                    from,
+                    to,
+                    text,
+                    status,
+                } => history.entry(key).or_default().push(ChatEntry::new(
+                    id,
+                    Message::new(from, to, text),
+                    status,
+                )),
+                Record::Status { key, id, status } => {
+                    set_status_in(&mut history, key, id, status);
+                }
+                Record::Sent { .. } | Record::Received { .. } => {}
+            }
+        }
+        Ok(history)
+    }
+
+    fn load_sent(&mut self) -> Result<HashMap<NodeId, u64>, ClientError> {
+        let mut sent = HashMap::new();
+        for record in self.records()? {
+            if let Record::Sent { key, count } = record {
+                sent.insert(key, count);
+            }
+        }
+        Ok(sent)
+    }
+
+    fn load_received(&mut self) -> Result<HashMap<NodeId, u64>, ClientError> {
+        let mut received = HashMap::new();
+        for record in self.records()? {
+            if let Record::Received { key, next } = record {
+                received.insert(key, next);
+            }
+        }
+        Ok(received)
+    }
+
+    fn flush(&mut self) -> Result<(), ClientError> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_all()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod history_tests {
+    use super::*;
+
+    fn entry(n: u128, from: NodeId, to: NodeId, text: &str) -> ChatEntry {
+        ChatEntry::new(
+            Uuid::from_u128(n),
+            Message::new(from, to, text.to_string()),
+            MessageStatus::Sent,
+        )
+    }
+
+    #[test]
+    /// Tests that appended messages and status changes are reloaded, skipping
+    /// truncated records
+    fn test_file_store_reload() {
+        let path = std::env::temp_dir().join("client-history-reload.jsonl");
+        let _ = std::fs::remove_file(&path);
+
+        let mut store = FileHistoryStore::open(&path).unwrap();
+        store.append(20, &entry(1, 20, 1, "Hello")).unwrap();
+        store.append(20, &entry(2, 1, 20, "Hi")).unwrap();
+        store
+            .set_status(20, Uuid::from_u128(2), MessageStatus::Delivered)
+            .unwrap();
+        store.set_sent(20, 1).unwrap();
+        store.set_received(20, 1).unwrap();
+        store.set_received(20, 2).unwrap();
+        store
+            .writer
+            .write_all(b"{\"Message\":{\"key\":20,\"fr")
+            .unwrap();
+        store.flush().unwrap();
+
+        let mut store = FileHistoryStore::open(&path).unwrap();
+        store.append(20, &entry(3, 20, 1, "Again")).unwrap();
+        let history = store.load().unwrap();
+        let chat = history.get(&20).unwrap();
+        assert_eq!(chat.len(), 3);
+        assert_eq!(chat[1].message.text, "Hi".to_string());
+        assert_eq!(chat[1].status, MessageStatus::Delivered);
+        assert_eq!(store.load_sent().unwrap(), HashMap::from([(20, 1)]));
+        assert_eq!(store.load_received().unwrap(), HashMap::from([(20, 2)]));
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -1,4 +1,23 @@
 #![allow(dead_code)]
 pub mod web_browser;
+pub mod async_client;
 pub mod chat_client;
+pub mod chat_message;
+pub mod client_directory;
+pub mod bookmarks;
+pub mod cache;
+pub mod catalog;
+pub mod disk_cache;
 pub mod errors;
+pub mod group;
+pub mod history;
+pub mod media_fetch;
+pub mod media_library;
+pub mod navigation;
+pub mod outbox;
+pub mod reorder;
+pub mod prefetch;
+pub mod request_tracker;
+pub mod server_health;
+pub mod ticker;
+pub mod types;
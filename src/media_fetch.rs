@@ -0,0 +1,140 @@
+use common::types::MediaFile;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
+use uuid::Uuid;
+use wg_internal::network::NodeId;
+
+/// FNV-1a over the media content, the checksum text servers may attach to media references.
+#[must_use]
+pub fn checksum(media: &MediaFile) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in media.content.iter().flatten() {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+#[derive(Deserialize)]
+struct ChecksummedRef {
+    id: Uuid,
+    #[serde(default)]
+    checksum: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct ChecksummedFile {
+    #[serde(default)]
+    media_refs: Vec<ChecksummedRef>,
+}
+
+/// Checksums a text server added to the media references of a serialized `TextFile`.
+#[must_use]
+pub fn ref_checksums(file_data: &[u8]) -> HashMap<Uuid, u64> {
+    serde_json::from_slice::<ChecksummedFile>(file_data)
+        .map(|f| {
+            f.media_refs
+                .into_iter()
+                .filter_map(|r| Some((r.id, r.checksum?)))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// State of the download of one referenced media.
+#[derive(Debug, Clone, Default)]
+pub struct MediaFetch {
+    pub checksum: Option<u64>,
+    /// Locations already asked, in order.
+    pub tried: Vec<NodeId>,
+    /// Set while waiting for a media server to be discovered.
+    pub parked: bool,
+}
+
+impl MediaFetch {
+    #[must_use]
+    pub fn new(location: NodeId, checksum: Option<u64>) -> Self {
+        Self {
+            checksum,
+            tried: vec![location],
+            parked: false,
+        }
+    }
+
+    /// Whether `media` matches the checksum of its reference, if any.
+    #[must_use]
+    pub fn verify(&self, media: &MediaFile) -> bool {
+        self.checksum.is_none_or(|c| c == checksum(media))
+    }
+
+    /// Picks the first of `servers` not tried yet, recording it.
+    pub fn next_location(&mut self, servers: impl IntoIterator<Item = NodeId>) -> Option<NodeId> {
+        let next = servers.into_iter().find(|s| !self.tried.contains(s))?;
+        self.tried.push(next);
+        Some(next)
+    }
+}
+
+/// Stands in for a media that has not arrived, so that partial files keep one
+/// entry per reference.
+#[must_use]
+pub fn placeholder(id: Uuid) -> MediaFile {
+    MediaFile {
+        id,
+        title: String::new(),
+        content: vec![],
+    }
+}
+
+/// Progress of the media of a text file being downloaded.
+#[derive(Debug, Clone)]
+pub struct FileDownload {
+    pub started: Instant,
+    /// Media no location could serve.
+    pub failed: HashSet<Uuid>,
+    pub partial_sent: bool,
+}
+
+impl FileDownload {
+    #[must_use]
+    pub fn new(started: Instant) -> Self {
+        Self {
+            started,
+            failed: HashSet::new(),
+            partial_sent: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod media_fetch_tests {
+    use super::*;
+
+    #[test]
+    /// Tests that checksums are read from the references and verified
+    fn test_ref_checksums() {
+        let media = MediaFile {
+            id: Uuid::from_u128(1),
+            title: "Image".to_string(),
+            content: vec![vec![1, 2], vec![3]],
+        };
+        let data = format!(
+            r#"{{"id":"{}","title":"A","content":"","media_refs":[{{"location":6,"id":"{}","checksum":{}}},{{"location":6,"id":"{}"}}]}}"#,
+            Uuid::from_u128(9),
+            media.id,
+            checksum(&media),
+            Uuid::from_u128(2),
+        );
+        let checksums = ref_checksums(data.as_bytes());
+        assert_eq!(checksums.len(), 1);
+
+        let fetch = MediaFetch::new(6, checksums.get(&media.id).copied());
+        assert!(fetch.verify(&media));
+        let tampered = MediaFile {
+            content: vec![vec![1, 2], vec![4]],
+            ..media
+        };
+        assert!(!fetch.verify(&tampered));
+    }
+}
@@ -0,0 +1,167 @@
+use common::types::MediaFile;
+use std::collections::{BTreeSet, HashMap};
+use uuid::Uuid;
+use wg_internal::network::NodeId;
+
+#[derive(Debug)]
+struct LibraryEntry {
+    file: MediaFile,
+    last_used: u64,
+}
+
+/// Media fetched on their own rather than for a text file, least recently
+/// used first out, with the media each media server is known to hold.
+#[derive(Debug)]
+pub struct MediaLibrary {
+    capacity: usize,
+    media: HashMap<Uuid, LibraryEntry>,
+    /// Learnt from media references and from the media servers' responses.
+    locations: HashMap<NodeId, BTreeSet<Uuid>>,
+    clock: u64,
+}
+
+impl Default for MediaLibrary {
+    fn default() -> Self {
+        Self::new(64)
+    }
+}
+
+impl MediaLibrary {
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            media: HashMap::new(),
+            locations: HashMap::new(),
+            clock: 0,
+        }
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    /// Stores `media`, returning the ids of the media evicted to make room.
+    pub fn insert(&mut self, media: MediaFile) -> Vec<Uuid> {
+        let last_used = self.tick();
+        self.media.insert(
+            media.id,
+            LibraryEntry {
+                file: media,
+                last_used,
+            },
+        );
+        self.evict()
+    }
+
+    pub fn get(&mut self, id: Uuid) -> Option<MediaFile> {
+        let last_used = self.tick();
+        let entry = self.media.get_mut(&id)?;
+        entry.last_used = last_used;
+        Some(entry.file.clone())
+    }
+
+    /// Removes the media `id`, now held by a cached text file.
+    pub fn take(&mut self, id: Uuid) -> Option<MediaFile> {
+        self.media.remove(&id).map(|e| e.file)
+    }
+
+    pub fn media_files(&self) -> impl Iterator<Item = &MediaFile> {
+        self.media.values().map(|e| &e.file)
+    }
+
+    pub fn clear(&mut self) {
+        self.media.clear();
+    }
+
+    fn evict(&mut self) -> Vec<Uuid> {
+        let mut evicted = vec![];
+        while self.media.len() > self.capacity {
+            let Some(victim) = self
+                .media
+                .iter()
+                .min_by_key(|(_, e)| e.last_used)
+                .map(|(id, _)| *id)
+            else {
+                break;
+            };
+            self.media.remove(&victim);
+            evicted.push(victim);
+        }
+        evicted
+    }
+
+    /// Records that `server` holds the media `id`.
+    pub fn locate(&mut self, server: NodeId, id: Uuid) {
+        self.locations.entry(server).or_default().insert(id);
+    }
+
+    /// Forgets the media `id` on `server`, which answered it does not have it.
+    pub fn forget(&mut self, server: NodeId, id: Uuid) {
+        if let Some(ids) = self.locations.get_mut(&server) {
+            ids.remove(&id);
+        }
+    }
+
+    /// Servers known to hold the media `id`, lowest id first.
+    #[must_use]
+    pub fn holders(&self, id: Uuid) -> Vec<NodeId> {
+        let mut servers = self
+            .locations
+            .iter()
+            .filter(|(_, ids)| ids.contains(&id))
+            .map(|(server, _)| *server)
+            .collect::<Vec<_>>();
+        servers.sort_unstable();
+        servers
+    }
+
+    /// Media known to be held by `server`.
+    #[must_use]
+    pub fn catalog(&self, server: NodeId) -> Vec<Uuid> {
+        self.locations
+            .get(&server)
+            .map(|ids| ids.iter().copied().collect())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod media_library_tests {
+    use super::*;
+
+    fn media(id: u128) -> MediaFile {
+        MediaFile {
+            id: Uuid::from_u128(id),
+            title: "Image".to_string(),
+            content: vec![vec![1]],
+        }
+    }
+
+    #[test]
+    /// Tests least recently used eviction and the per-server catalog
+    fn test_library() {
+        let mut library = MediaLibrary::new(2);
+        library.insert(media(1));
+        library.insert(media(2));
+        assert!(library.get(Uuid::from_u128(1)).is_some());
+        assert_eq!(library.insert(media(3)), vec![Uuid::from_u128(2)]);
+
+        library.locate(6, Uuid::from_u128(3));
+        library.locate(6, Uuid::from_u128(1));
+        library.locate(7, Uuid::from_u128(3));
+        assert_eq!(library.holders(Uuid::from_u128(3)), vec![6, 7]);
+        library.forget(7, Uuid::from_u128(3));
+        assert_eq!(
+            library.catalog(6),
+            vec![Uuid::from_u128(1), Uuid::from_u128(3)]
+        );
+        assert!(library.catalog(7).is_empty());
+    }
+}
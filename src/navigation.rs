@@ -0,0 +1,138 @@
+use uuid::Uuid;
+
+/// A reference to another text file in the content of a text file, written
+/// `[label](<text file uuid>)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Link {
+    pub label: String,
+    pub target: Uuid,
+}
+
+/// Every well-formed link in `content`, in order of appearance.
+#[must_use]
+pub fn links(content: &str) -> Vec<Link> {
+    let mut found = vec![];
+    let mut rest = content;
+    while let Some(open) = rest.find('[') {
+        rest = &rest[open + 1..];
+        let Some((label, after)) = rest.split_once("](") else {
+            break;
+        };
+        // a `[` inside the label starts the next candidate instead
+        if label.contains('[') {
+            continue;
+        }
+        let Some((target, after)) = after.split_once(')') else {
+            break;
+        };
+        if let Ok(target) = Uuid::parse_str(target.trim()) {
+            found.push(Link {
+                label: label.to_string(),
+                target,
+            });
+            rest = after;
+        }
+    }
+    found
+}
+
+/// Pages visited by a browser, with back and forward stacks.
+#[derive(Debug, Clone, Default)]
+pub struct Navigation {
+    back: Vec<Uuid>,
+    current: Option<Uuid>,
+    forward: Vec<Uuid>,
+}
+
+impl Navigation {
+    /// Opens `page`, dropping the forward history as a browser does.
+    pub fn visit(&mut self, page: Uuid) {
+        if self.current == Some(page) {
+            return;
+        }
+        if let Some(current) = self.current.replace(page) {
+            self.back.push(current);
+        }
+        self.forward.clear();
+    }
+
+    pub fn back(&mut self) -> Option<Uuid> {
+        let page = self.back.pop()?;
+        if let Some(current) = self.current.replace(page) {
+            self.forward.push(current);
+        }
+        Some(page)
+    }
+
+    pub fn forward(&mut self) -> Option<Uuid> {
+        let page = self.forward.pop()?;
+        if let Some(current) = self.current.replace(page) {
+            self.back.push(current);
+        }
+        Some(page)
+    }
+
+    #[must_use]
+    pub fn current(&self) -> Option<Uuid> {
+        self.current
+    }
+
+    /// Pages behind the current one, most recent last.
+    #[must_use]
+    pub fn back_history(&self) -> &[Uuid] {
+        &self.back
+    }
+
+    /// Pages ahead of the current one, the next one first.
+    #[must_use]
+    pub fn forward_history(&self) -> Vec<Uuid> {
+        self.forward.iter().rev().copied().collect()
+    }
+}
+
+#[cfg(test)]
+mod navigation_tests {
+    use super::*;
+
+    #[test]
+    /// Tests back and forward moves and that visiting drops the forward history
+    fn test_back_forward() {
+        let (a, b, c) = (Uuid::from_u128(1), Uuid::from_u128(2), Uuid::from_u128(3));
+        let mut nav = Navigation::default();
+        nav.visit(a);
+        nav.visit(b);
+        nav.visit(c);
+
+        assert_eq!(nav.back(), Some(b));
+        assert_eq!(nav.back(), Some(a));
+        assert_eq!(nav.back(), None);
+        assert_eq!(nav.forward_history(), vec![b, c]);
+        assert_eq!(nav.forward(), Some(b));
+
+        nav.visit(a);
+        assert_eq!(nav.back_history(), &[a, b]);
+        assert_eq!(nav.forward(), None);
+        assert_eq!(nav.current(), Some(a));
+    }
+
+    #[test]
+    /// Tests that only links to a valid uuid are recognised
+    fn test_links() {
+        let target = Uuid::from_u128(7);
+        let content =
+            format!("See [the [other]({target}) article], [broken](nope) and [x]( {target} ).");
+        assert_eq!(
+            links(&content),
+            vec![
+                Link {
+                    label: "other".to_string(),
+                    target
+                },
+                Link {
+                    label: "x".to_string(),
+                    target
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,244 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use uuid::Uuid;
+use wg_internal::network::NodeId;
+
+#[derive(Debug, Clone, Copy)]
+pub struct OutboxConfig {
+    /// Time after which a queued message fails instead of waiting any longer.
+    pub max_age: Duration,
+    /// Messages queued at once, across every destination.
+    pub max_size: usize,
+}
+
+impl Default for OutboxConfig {
+    fn default() -> Self {
+        Self {
+            max_age: Duration::from_secs(600),
+            max_size: 256,
+        }
+    }
+}
+
+/// A message waiting for a chat server that knows its recipient.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueuedMessage {
+    pub to: NodeId,
+    /// Id of the carried payload, shared by the copies of a group message.
+    pub id: Uuid,
+    /// Encoded `ChatPayload`.
+    pub payload: String,
+    /// Wall clock time, so that the age of a message survives restarts.
+    pub queued_at: SystemTime,
+    /// Command that queued the message, meaningless after a restart.
+    #[serde(skip)]
+    pub correlation: Option<u64>,
+}
+
+/// Messages waiting to be sent, first in first out for each recipient, saved
+/// to a JSON file when opened from one.
+#[derive(Debug, Default)]
+pub struct Outbox {
+    config: OutboxConfig,
+    path: Option<PathBuf>,
+    queues: BTreeMap<NodeId, VecDeque<QueuedMessage>>,
+}
+
+impl Outbox {
+    #[must_use]
+    pub fn new(config: OutboxConfig) -> Self {
+        Self {
+            config,
+            path: None,
+            queues: BTreeMap::new(),
+        }
+    }
+
+    /// Loads the messages saved at `path`, starting empty when there are none.
+    pub fn open(path: impl Into<PathBuf>, config: OutboxConfig) -> io::Result<Self> {
+        let path = path.into();
+        let stored: Vec<QueuedMessage> = match fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data).map_err(io::Error::other)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e),
+        };
+        let mut outbox = Self {
+            path: Some(path),
+            ..Self::new(config)
+        };
+        for message in stored {
+            outbox
+                .queues
+                .entry(message.to)
+                .or_default()
+                .push_back(message);
+        }
+        Ok(outbox)
+    }
+
+    #[must_use]
+    pub fn config(&self) -> OutboxConfig {
+        self.config
+    }
+
+    pub fn set_config(&mut self, config: OutboxConfig) {
+        self.config = config;
+    }
+
+    /// Writes the queued messages, replacing the saved file atomically like
+    /// `DiskCache::store`. Changes are only written here, so a batch of them
+    /// costs one write.
+    pub fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let data = serde_json::to_vec(&self.messages()).map_err(io::Error::other)?;
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, data)?;
+        fs::rename(tmp, path)
+    }
+
+    /// Queues `message` behind the others for its recipient, refusing it when
+    /// the outbox is full.
+    pub fn push(&mut self, message: QueuedMessage) -> bool {
+        if self.len() >= self.config.max_size {
+            return false;
+        }
+        self.queues
+            .entry(message.to)
+            .or_default()
+            .push_back(message);
+        true
+    }
+
+    /// Queues `message` ahead of the others for its recipient, as it was sent
+    /// before them, refusing it when the outbox is full.
+    pub fn push_front(&mut self, message: QueuedMessage) -> bool {
+        if self.len() >= self.config.max_size {
+            return false;
+        }
+        self.queues
+            .entry(message.to)
+            .or_default()
+            .push_front(message);
+        true
+    }
+
+    /// Removes the messages queued for `to`, oldest first.
+    pub fn take(&mut self, to: NodeId) -> Vec<QueuedMessage> {
+        self.queues.remove(&to).map(Vec::from).unwrap_or_default()
+    }
+
+    /// Recipients with queued messages, lowest id first.
+    #[must_use]
+    pub fn destinations(&self) -> Vec<NodeId> {
+        self.queues.keys().copied().collect()
+    }
+
+    /// Removes the messages queued for longer than the maximum age.
+    pub fn expire(&mut self, now: SystemTime) -> Vec<QueuedMessage> {
+        let max_age = self.config.max_age;
+        let mut expired = vec![];
+        for queue in self.queues.values_mut() {
+            // a clock set backwards makes messages look new rather than expired
+            while let Some(front) = queue.front()
+                && now.duration_since(front.queued_at).unwrap_or_default() >= max_age
+            {
+                expired.extend(queue.pop_front());
+            }
+        }
+        self.queues.retain(|_, q| !q.is_empty());
+        expired
+    }
+
+    /// Removes every queued copy of the message `id`.
+    pub fn cancel(&mut self, id: Uuid) -> Vec<QueuedMessage> {
+        let mut cancelled = vec![];
+        for queue in self.queues.values_mut() {
+            queue.retain(|m| {
+                if m.id == id {
+                    cancelled.push(m.clone());
+                }
+                m.id != id
+            });
+        }
+        self.queues.retain(|_, q| !q.is_empty());
+        cancelled
+    }
+
+    /// Queued messages by recipient, oldest first for each.
+    #[must_use]
+    pub fn messages(&self) -> Vec<QueuedMessage> {
+        self.queues.values().flatten().cloned().collect()
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.queues.values().map(VecDeque::len).sum()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.queues.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod outbox_tests {
+    use super::*;
+
+    fn queued(to: NodeId, id: u128, queued_at: SystemTime) -> QueuedMessage {
+        QueuedMessage {
+            to,
+            id: Uuid::from_u128(id),
+            payload: format!("message {id}"),
+            queued_at,
+            correlation: None,
+        }
+    }
+
+    #[test]
+    /// Tests per-recipient order, the size limit, expiry and cancellation
+    fn test_queue() {
+        let mut outbox = Outbox::new(OutboxConfig {
+            max_age: Duration::from_secs(60),
+            max_size: 3,
+        });
+        let start = SystemTime::now();
+        assert!(outbox.push(queued(10, 1, start)));
+        assert!(outbox.push(queued(11, 2, start + Duration::from_secs(30))));
+        assert!(outbox.push(queued(10, 3, start + Duration::from_secs(30))));
+        assert!(!outbox.push(queued(12, 4, start)));
+        assert_eq!(outbox.destinations(), vec![10, 11]);
+
+        let expired = outbox.expire(start + Duration::from_secs(60));
+        assert_eq!(expired, vec![queued(10, 1, start)]);
+        assert_eq!(outbox.cancel(Uuid::from_u128(2)).len(), 1);
+        assert!(outbox.push_front(queued(10, 5, start)));
+        let ids = outbox.take(10).iter().map(|m| m.id).collect::<Vec<_>>();
+        assert_eq!(ids, vec![Uuid::from_u128(5), Uuid::from_u128(3)]);
+        assert!(outbox.is_empty());
+    }
+
+    #[test]
+    /// Tests that queued messages are reloaded from their file in order
+    fn test_reload() {
+        let path = std::env::temp_dir().join("client-outbox-reload.json");
+        let _ = fs::remove_file(&path);
+        let start = SystemTime::now();
+
+        let mut outbox = Outbox::open(&path, OutboxConfig::default()).unwrap();
+        outbox.push(queued(10, 1, start));
+        outbox.push(queued(10, 2, start));
+        outbox.save().unwrap();
+
+        let mut reloaded = Outbox::open(&path, OutboxConfig::default()).unwrap();
+        let ids = reloaded.take(10).iter().map(|m| m.id).collect::<Vec<_>>();
+        assert_eq!(ids, vec![Uuid::from_u128(1), Uuid::from_u128(2)]);
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::{HashSet, VecDeque};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy)]
+pub struct PrefetchConfig {
+    /// Text files downloaded in the background at once, media included.
+    pub max_in_flight: usize,
+    /// Text files waiting for a slot, the extra ones are not prefetched.
+    pub max_queued: usize,
+}
+
+impl PrefetchConfig {
+    pub const DISABLED: Self = Self {
+        max_in_flight: 0,
+        max_queued: 0,
+    };
+}
+
+impl Default for PrefetchConfig {
+    fn default() -> Self {
+        Self {
+            max_in_flight: 2,
+            max_queued: 8,
+        }
+    }
+}
+
+/// Text files downloaded ahead of time, without the controller asking for them.
+#[derive(Debug)]
+pub struct Prefetcher {
+    config: PrefetchConfig,
+    queue: VecDeque<Uuid>,
+    in_flight: HashSet<Uuid>,
+    /// Cancelled while in flight, whatever still arrives for them is dropped.
+    cancelled: HashSet<Uuid>,
+    /// Queued for being popular, never queued as such again.
+    popular: HashSet<Uuid>,
+}
+
+impl Prefetcher {
+    #[must_use]
+    pub fn new(config: PrefetchConfig) -> Self {
+        Self {
+            config,
+            queue: VecDeque::new(),
+            in_flight: HashSet::new(),
+            cancelled: HashSet::new(),
+            popular: HashSet::new(),
+        }
+    }
+
+    pub fn set_config(&mut self, config: PrefetchConfig) {
+        self.config = config;
+    }
+
+    /// Queues `id` unless it is already known or the queue is full.
+    pub fn enqueue(&mut self, id: Uuid) -> bool {
+        if self.queue.len() >= self.config.max_queued
+            || self.queue.contains(&id)
+            || self.is_background(id)
+        {
+            return false;
+        }
+        self.queue.push_back(id);
+        true
+    }
+
+    /// Queues `id` for being popular, once for the lifetime of the prefetcher.
+    pub fn enqueue_popular(&mut self, id: Uuid) -> bool {
+        !self.popular.contains(&id) && self.enqueue(id) && self.popular.insert(id)
+    }
+
+    /// Whether nothing is queued nor in flight while the budget allows downloads.
+    #[must_use]
+    pub fn is_starved(&self) -> bool {
+        self.config.max_queued > 0 && self.queue.is_empty() && self.in_flight.is_empty()
+    }
+
+    /// The next queued file to download, if the budget allows one more.
+    pub fn start_next(&mut self) -> Option<Uuid> {
+        if self.in_flight.len() >= self.config.max_in_flight {
+            return None;
+        }
+        let id = self.queue.pop_front()?;
+        self.in_flight.insert(id);
+        Some(id)
+    }
+
+    /// Whether `id` is being downloaded for the prefetcher rather than the controller.
+    #[must_use]
+    pub fn is_background(&self, id: Uuid) -> bool {
+        self.in_flight.contains(&id) || self.cancelled.contains(&id)
+    }
+
+    /// Whether `id` was cancelled while in flight.
+    #[must_use]
+    pub fn is_cancelled(&self, id: Uuid) -> bool {
+        self.cancelled.contains(&id)
+    }
+
+    /// Stops following `id`, returning whether it was a background download.
+    pub fn finish(&mut self, id: Uuid) -> bool {
+        self.in_flight.remove(&id) | self.cancelled.remove(&id)
+    }
+
+    /// Hands `id` over to the controller, which asked for it, returning whether
+    /// its download is still in progress.
+    pub fn promote(&mut self, id: Uuid) -> bool {
+        self.queue.retain(|q| *q != id);
+        self.cancelled.remove(&id);
+        self.in_flight.remove(&id)
+    }
+
+    /// Drops the queue and returns the downloads in flight, which no longer
+    /// count towards the budget.
+    pub fn cancel(&mut self) -> Vec<Uuid> {
+        self.queue.clear();
+        let cancelled = self.in_flight.drain().collect::<Vec<_>>();
+        self.cancelled.extend(&cancelled);
+        cancelled
+    }
+}
+
+#[cfg(test)]
+mod prefetch_tests {
+    use super::*;
+
+    #[test]
+    /// Tests the queue and in-flight budgets, promotion and cancellation
+    fn test_budget() {
+        let mut prefetcher = Prefetcher::new(PrefetchConfig {
+            max_in_flight: 1,
+            max_queued: 2,
+        });
+        let (a, b, c) = (Uuid::from_u128(1), Uuid::from_u128(2), Uuid::from_u128(3));
+        assert!(prefetcher.enqueue(a));
+        assert!(!prefetcher.enqueue(a));
+        assert!(prefetcher.enqueue(b));
+        assert!(!prefetcher.enqueue(c));
+
+        assert_eq!(prefetcher.start_next(), Some(a));
+        assert_eq!(prefetcher.start_next(), None);
+        assert!(!prefetcher.promote(b));
+        assert!(prefetcher.enqueue(c));
+
+        assert_eq!(prefetcher.cancel(), vec![a]);
+        assert!(prefetcher.is_background(a));
+        assert_eq!(prefetcher.start_next(), None);
+        assert!(prefetcher.finish(a));
+        assert!(!prefetcher.is_background(a));
+    }
+
+    #[test]
+    /// Tests that a popular file is queued once only
+    fn test_popular() {
+        let mut prefetcher = Prefetcher::new(PrefetchConfig::default());
+        let a = Uuid::from_u128(1);
+        assert!(prefetcher.is_starved());
+        assert!(prefetcher.enqueue_popular(a));
+        assert!(!prefetcher.is_starved());
+        assert_eq!(prefetcher.start_next(), Some(a));
+        assert!(prefetcher.finish(a));
+        assert!(!prefetcher.enqueue_popular(a));
+        assert!(prefetcher.enqueue(a));
+    }
+}
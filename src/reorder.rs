@@ -0,0 +1,195 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+use std::time::{Duration, Instant};
+use wg_internal::network::NodeId;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ReorderConfig {
+    /// Time a message waits for the ones sent before it, after which the
+    /// missing ones are skipped.
+    pub max_wait: Duration,
+    /// Messages held for a conversation, the oldest gap being skipped beyond it.
+    pub max_held: usize,
+}
+
+impl Default for ReorderConfig {
+    fn default() -> Self {
+        Self {
+            max_wait: Duration::from_secs(5),
+            max_held: 64,
+        }
+    }
+}
+
+/// Gaps remembered per conversation, telling late messages from renumbered ones.
+const MAX_SKIPPED: usize = 16;
+
+#[derive(Debug)]
+struct Conversation<T> {
+    /// Sequence number of the next message to hand over.
+    next: u64,
+    held: BTreeMap<u64, (T, Instant)>,
+    /// Sequence numbers given up on, the most recent gaps last.
+    skipped: Vec<Range<u64>>,
+}
+
+impl<T> Conversation<T> {
+    // hands over the messages following `next` without a gap
+    fn release(&mut self, released: &mut Vec<T>) {
+        while let Some((item, _)) = self.held.remove(&self.next) {
+            released.push(item);
+            self.next += 1;
+        }
+    }
+
+    // gives up on the messages missing before the first held one
+    fn skip_gap(&mut self, released: &mut Vec<T>) {
+        if let Some(first) = self.held.keys().next().copied() {
+            if first > self.next {
+                if self.skipped.len() == MAX_SKIPPED {
+                    self.skipped.remove(0);
+                }
+                self.skipped.push(self.next..first);
+            }
+            self.next = first;
+            self.release(released);
+        }
+    }
+}
+
+/// Messages received ahead of their turn, held per peer until the ones sent
+/// before them arrive or take too long.
+#[derive(Debug)]
+pub struct ReorderBuffer<T> {
+    config: ReorderConfig,
+    conversations: HashMap<NodeId, Conversation<T>>,
+}
+
+impl<T> ReorderBuffer<T> {
+    #[must_use]
+    pub fn new(config: ReorderConfig) -> Self {
+        Self {
+            config,
+            conversations: HashMap::new(),
+        }
+    }
+
+    pub fn set_config(&mut self, config: ReorderConfig) {
+        self.config = config;
+    }
+
+    /// Sequence number of the next message expected from `peer`, if followed.
+    #[must_use]
+    pub fn next(&self, peer: NodeId) -> Option<u64> {
+        self.conversations.get(&peer).map(|c| c.next)
+    }
+
+    /// Starts following `peer`, whose next message is expected to be `next`.
+    pub fn start(&mut self, peer: NodeId, next: u64) {
+        self.conversations.entry(peer).or_insert(Conversation {
+            next,
+            held: BTreeMap::new(),
+            skipped: vec![],
+        });
+    }
+
+    /// Takes the message `seq` from `peer`, never taken before, returning the
+    /// messages now in order; a second copy of a held one is dropped. A message
+    /// from a skipped gap is returned straight away, while any other one below
+    /// the next expected means the sender numbers from scratch again, the held
+    /// messages being handed over before the conversation restarts from it.
+    pub fn push(&mut self, peer: NodeId, seq: u64, item: T, now: Instant) -> Vec<T> {
+        let conversation = self.conversations.entry(peer).or_insert(Conversation {
+            next: seq,
+            held: BTreeMap::new(),
+            skipped: vec![],
+        });
+        let mut released = vec![];
+        if seq < conversation.next {
+            if conversation.skipped.iter().any(|gap| gap.contains(&seq)) {
+                return vec![item];
+            }
+            let held = std::mem::take(&mut conversation.held);
+            released.extend(held.into_values().map(|(item, _)| item));
+            conversation.skipped.clear();
+            conversation.next = seq;
+        }
+        conversation.held.entry(seq).or_insert((item, now));
+        conversation.release(&mut released);
+        if conversation.held.len() > self.config.max_held {
+            conversation.skip_gap(&mut released);
+        }
+        released
+    }
+
+    /// Skips the gaps that held messages waited on for too long, returning the
+    /// messages released with their peer.
+    pub fn expire(&mut self, now: Instant) -> Vec<(NodeId, T)> {
+        let max_wait = self.config.max_wait;
+        let mut expired = vec![];
+        for (peer, conversation) in &mut self.conversations {
+            let mut released = vec![];
+            while conversation
+                .held
+                .values()
+                .next()
+                .is_some_and(|(_, held_at)| now.duration_since(*held_at) >= max_wait)
+            {
+                conversation.skip_gap(&mut released);
+            }
+            expired.extend(released.into_iter().map(|item| (*peer, item)));
+        }
+        expired
+    }
+}
+
+#[cfg(test)]
+mod reorder_tests {
+    use super::*;
+
+    #[test]
+    /// Tests reordering, duplicate copies of held messages and skipped gaps
+    fn test_reorder() {
+        let mut buffer = ReorderBuffer::new(ReorderConfig {
+            max_wait: Duration::from_secs(5),
+            max_held: 2,
+        });
+        let start = Instant::now();
+        buffer.start(10, 0);
+        assert!(buffer.push(10, 1, "b", start).is_empty());
+        assert!(buffer.push(10, 1, "b", start).is_empty());
+        assert_eq!(buffer.push(10, 0, "a", start), vec!["a", "b"]);
+
+        assert!(buffer.push(10, 3, "d", start).is_empty());
+        assert!(buffer.expire(start + Duration::from_secs(4)).is_empty());
+        assert_eq!(
+            buffer.expire(start + Duration::from_secs(5)),
+            vec![(10, "d")]
+        );
+
+        assert!(buffer.push(10, 5, "f", start).is_empty());
+        assert!(buffer.push(10, 6, "g", start).is_empty());
+        assert_eq!(buffer.push(10, 8, "i", start), vec!["f", "g"]);
+    }
+
+    #[test]
+    /// Tests that a message from a skipped gap is handed over late, while a
+    /// lower number outside of one restarts the conversation
+    fn test_restart() {
+        let mut buffer = ReorderBuffer::new(ReorderConfig::default());
+        let start = Instant::now();
+        buffer.start(10, 1);
+        assert!(buffer.push(10, 2, "c", start).is_empty());
+        assert_eq!(
+            buffer.expire(start + Duration::from_secs(5)),
+            vec![(10, "c")]
+        );
+        assert_eq!(buffer.push(10, 1, "b", start), vec!["b"]);
+        assert_eq!(buffer.next(10), Some(3));
+
+        // the sender lost its counter and numbers from zero again
+        assert!(buffer.push(10, 5, "f", start).is_empty());
+        assert_eq!(buffer.push(10, 0, "x", start), vec!["f", "x"]);
+        assert_eq!(buffer.next(10), Some(1));
+    }
+}
@@ -0,0 +1,313 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use wg_internal::network::NodeId;
+
+/// How long a client waits for a response before retrying, re-routing and giving up.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Wait before the first retry.
+    pub timeout: Duration,
+    /// Factor applied to the wait after every retry.
+    pub backoff: u32,
+    /// Retries attempted before the request is reported as timed out.
+    pub max_retries: u32,
+    /// Time since the first send after which the destination is re-resolved.
+    pub reroute_after: Duration,
+}
+
+impl RetryPolicy {
+    /// Time from the first send after which a request that got no response
+    /// times out.
+    #[must_use]
+    pub fn give_up_after(&self) -> Duration {
+        let mut wait = self.timeout;
+        let mut total = Duration::ZERO;
+        for _ in 0..=self.max_retries {
+            total += wait;
+            wait *= self.backoff.max(1);
+        }
+        total
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(2),
+            backoff: 2,
+            max_retries: 3,
+            reroute_after: Duration::from_secs(5),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TrackedRequest<R> {
+    request: R,
+    destination: NodeId,
+    retries: u32,
+    first_sent: Instant,
+    deadline: Instant,
+    wait: Duration,
+    rerouted: bool,
+    correlation: Option<u64>,
+}
+
+/// What the owner of a [`RequestTracker`] has to do with a request whose deadline passed.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Expiry<R> {
+    /// Send the request again to the same destination.
+    Retry {
+        session_id: u64,
+        request: R,
+        destination: NodeId,
+    },
+    /// Send the request again, through a different destination if one is known.
+    Reroute {
+        session_id: u64,
+        request: R,
+        destination: NodeId,
+    },
+    /// No retries left, the request was dropped.
+    TimedOut {
+        request: R,
+        destination: NodeId,
+        correlation: Option<u64>,
+    },
+}
+
+/// Session ids of tracked requests have the top bit set, the routing handler
+/// numbering the sends that are not tracked from zero.
+const TRACKED_SESSION_BIT: u64 = 1 << 63;
+
+/// Outstanding requests keyed by the session id they were sent with.
+#[derive(Debug)]
+pub struct RequestTracker<R> {
+    policy: RetryPolicy,
+    requests: HashMap<u64, TrackedRequest<R>>,
+    next_session_id: u64,
+}
+
+impl<R: Clone> RequestTracker<R> {
+    #[must_use]
+    pub fn new(policy: RetryPolicy) -> Self {
+        Self {
+            policy,
+            requests: HashMap::new(),
+            next_session_id: TRACKED_SESSION_BIT,
+        }
+    }
+
+    pub fn set_policy(&mut self, policy: RetryPolicy) {
+        self.policy = policy;
+    }
+
+    #[must_use]
+    pub fn policy(&self) -> RetryPolicy {
+        self.policy
+    }
+
+    /// Starts tracking `request`, returning the session id it has to be sent with;
+    /// `correlation` identifies the controller command it was sent for, if any.
+    pub fn track(
+        &mut self,
+        request: R,
+        destination: NodeId,
+        correlation: Option<u64>,
+        now: Instant,
+    ) -> u64 {
+        let session_id = self.next_session_id;
+        self.next_session_id = self.next_session_id.wrapping_add(1) | TRACKED_SESSION_BIT;
+        self.requests.insert(
+            session_id,
+            TrackedRequest {
+                request,
+                destination,
+                retries: 0,
+                first_sent: now,
+                deadline: now + self.policy.timeout,
+                wait: self.policy.timeout,
+                rerouted: false,
+                correlation,
+            },
+        );
+        session_id
+    }
+
+    /// Points a tracked request to a new destination after a re-route.
+    pub fn retarget(&mut self, session_id: u64, destination: NodeId) {
+        if let Some(tracked) = self.requests.get_mut(&session_id) {
+            tracked.destination = destination;
+        }
+    }
+
+    /// Correlation id of the request sent with `session_id`.
+    #[must_use]
+    pub fn correlation(&self, session_id: u64) -> Option<u64> {
+        self.requests.get(&session_id)?.correlation
+    }
+
+    pub fn resolve(&mut self, session_id: u64) -> Option<R> {
+        self.requests.remove(&session_id).map(|t| t.request)
+    }
+
+    /// Stops tracking every request matching `pred`, returning them.
+    pub fn resolve_by(&mut self, pred: impl Fn(&R, NodeId) -> bool) -> Vec<R> {
+        self.resolve_correlated_by(pred)
+            .into_iter()
+            .map(|(request, _)| request)
+            .collect()
+    }
+
+    /// Like [`Self::resolve_by`], returning the correlation id of each request
+    /// too, oldest request first.
+    pub fn resolve_correlated_by(
+        &mut self,
+        pred: impl Fn(&R, NodeId) -> bool,
+    ) -> Vec<(R, Option<u64>)> {
+        let mut matching = self
+            .requests
+            .iter()
+            .filter(|(_, t)| pred(&t.request, t.destination))
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        matching.sort_unstable();
+        matching
+            .into_iter()
+            .filter_map(|id| self.requests.remove(&id))
+            .map(|t| (t.request, t.correlation))
+            .collect()
+    }
+
+    /// Whether any tracked request matches `pred`.
+    pub fn any(&self, pred: impl Fn(&R, NodeId) -> bool) -> bool {
+        self.requests
+            .values()
+            .any(|t| pred(&t.request, t.destination))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.requests.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.requests.len()
+    }
+
+    /// Advances every request whose deadline passed at `now`.
+    pub fn poll(&mut self, now: Instant) -> Vec<Expiry<R>> {
+        let expired = self
+            .requests
+            .iter()
+            .filter(|(_, t)| t.deadline <= now)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+
+        let mut actions = vec![];
+        for session_id in expired {
+            let Some(tracked) = self.requests.get_mut(&session_id) else {
+                continue;
+            };
+            if tracked.retries >= self.policy.max_retries {
+                if let Some(tracked) = self.requests.remove(&session_id) {
+                    actions.push(Expiry::TimedOut {
+                        request: tracked.request,
+                        destination: tracked.destination,
+                        correlation: tracked.correlation,
+                    });
+                }
+                continue;
+            }
+            tracked.retries += 1;
+            tracked.wait *= self.policy.backoff.max(1);
+            tracked.deadline = now + tracked.wait;
+            let request = tracked.request.clone();
+            let destination = tracked.destination;
+            if !tracked.rerouted && now - tracked.first_sent >= self.policy.reroute_after {
+                tracked.rerouted = true;
+                actions.push(Expiry::Reroute {
+                    session_id,
+                    request,
+                    destination,
+                });
+            } else {
+                actions.push(Expiry::Retry {
+                    session_id,
+                    request,
+                    destination,
+                });
+            }
+        }
+        actions
+    }
+}
+
+#[cfg(test)]
+mod request_tracker_tests {
+    use super::*;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            timeout: Duration::from_secs(1),
+            backoff: 2,
+            max_retries: 2,
+            reroute_after: Duration::from_secs(3),
+        }
+    }
+
+    #[test]
+    /// Tests retry with backoff, re-route and final timeout
+    fn test_retry_reroute_timeout() {
+        let mut tracker = RequestTracker::new(policy());
+        let start = Instant::now();
+        let session_id = tracker.track("req", 5, Some(42), start);
+        assert_ne!(session_id & TRACKED_SESSION_BIT, 0);
+
+        assert!(tracker.poll(start).is_empty());
+        assert_eq!(
+            tracker.poll(start + Duration::from_secs(1)),
+            vec![Expiry::Retry {
+                session_id,
+                request: "req",
+                destination: 5
+            }]
+        );
+        // backoff doubled the wait to 2s
+        assert!(tracker.poll(start + Duration::from_secs(2)).is_empty());
+        assert_eq!(
+            tracker.poll(start + Duration::from_secs(3)),
+            vec![Expiry::Reroute {
+                session_id,
+                request: "req",
+                destination: 5
+            }]
+        );
+        tracker.retarget(session_id, 6);
+        assert_eq!(
+            tracker.poll(start + Duration::from_secs(7)),
+            vec![Expiry::TimedOut {
+                request: "req",
+                destination: 6,
+                correlation: Some(42)
+            }]
+        );
+        assert!(tracker.is_empty());
+    }
+
+    #[test]
+    /// Tests that resolved requests are no longer polled
+    fn test_resolve() {
+        let mut tracker = RequestTracker::new(policy());
+        let start = Instant::now();
+        let first = tracker.track("a", 5, None, start);
+        let second = tracker.track("b", 6, Some(7), start);
+
+        assert_eq!(tracker.correlation(second), Some(7));
+        assert_eq!(tracker.resolve(first), Some("a"));
+        assert_eq!(
+            tracker.resolve_correlated_by(|_, dest| dest == 6),
+            vec![("b", Some(7))]
+        );
+        assert!(tracker.poll(start + Duration::from_secs(10)).is_empty());
+    }
+}
@@ -0,0 +1,63 @@
+use std::collections::HashMap;
+use wg_internal::network::NodeId;
+
+/// Consecutive failures of the servers a client talks to, a server being
+/// considered dead once they reach the limit.
+#[derive(Debug)]
+pub struct ServerHealth {
+    max_failures: u32,
+    failures: HashMap<NodeId, u32>,
+}
+
+impl Default for ServerHealth {
+    fn default() -> Self {
+        Self::new(3)
+    }
+}
+
+impl ServerHealth {
+    #[must_use]
+    pub fn new(max_failures: u32) -> Self {
+        Self {
+            max_failures: max_failures.max(1),
+            failures: HashMap::new(),
+        }
+    }
+
+    pub fn set_max_failures(&mut self, max_failures: u32) {
+        self.max_failures = max_failures.max(1);
+    }
+
+    /// The server answered, so its failures so far were transient.
+    pub fn record_success(&mut self, server: NodeId) {
+        self.failures.remove(&server);
+    }
+
+    /// Counts a request the server never answered, returning whether the server
+    /// just reached the limit; it is forgotten then.
+    pub fn record_failure(&mut self, server: NodeId) -> bool {
+        let failures = self.failures.entry(server).or_default();
+        *failures += 1;
+        if *failures < self.max_failures {
+            return false;
+        }
+        self.failures.remove(&server);
+        true
+    }
+}
+
+#[cfg(test)]
+mod server_health_tests {
+    use super::*;
+
+    #[test]
+    /// Tests that only consecutive failures make a server dead
+    fn test_consecutive_failures() {
+        let mut health = ServerHealth::new(2);
+        assert!(!health.record_failure(5));
+        health.record_success(5);
+        assert!(!health.record_failure(5));
+        assert!(health.record_failure(5));
+        assert!(!health.record_failure(5));
+    }
+}
@@ -0,0 +1,58 @@
+use common::types::Command;
+use crossbeam_channel::{Receiver, select, tick, unbounded};
+use std::thread;
+use std::time::Duration;
+
+/// How often a client checks its timers when nothing else wakes it up.
+pub const TICK_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Forwards `commands` to the returned receiver, adding the command built by
+/// `make_tick` every `interval` so that retries, timeouts and expiries run on
+/// an idle client. The thread stops once the controller or the client is gone.
+pub fn with_ticks(
+    commands: Receiver<Box<dyn Command>>,
+    interval: Duration,
+    make_tick: fn() -> Box<dyn Command>,
+) -> Receiver<Box<dyn Command>> {
+    let (send, recv) = unbounded();
+    let ticks = tick(interval);
+    thread::spawn(move || {
+        loop {
+            let cmd = select! {
+                recv(commands) -> cmd => match cmd {
+                    Ok(cmd) => cmd,
+                    Err(_) => return,
+                },
+                recv(ticks) -> _ => make_tick(),
+            };
+            if send.send(cmd).is_err() {
+                return;
+            }
+        }
+    });
+    recv
+}
+
+#[cfg(test)]
+mod ticker_tests {
+    use super::*;
+    use crate::types::ChatClientCommand;
+
+    #[test]
+    /// Tests that ticks arrive without commands and stop with the controller
+    fn test_ticks() {
+        let (controller_send, controller_recv) = unbounded();
+        let commands = with_ticks(controller_recv, Duration::from_millis(1), || {
+            Box::new(ChatClientCommand::Tick)
+        });
+        let cmd = commands.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert!(matches!(
+            cmd.as_any().downcast_ref::<ChatClientCommand>(),
+            Some(ChatClientCommand::Tick)
+        ));
+
+        drop(controller_send);
+        let remaining = commands.iter().take(1000).count();
+        assert!(remaining < 1000);
+    }
+}
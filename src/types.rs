@@ -0,0 +1,321 @@
+use crate::bookmarks::Bookmark;
+use crate::cache::CacheStats;
+use crate::catalog::CatalogEntry;
+use crate::chat_message::{ChatEntry, MessageStatus};
+use crate::errors::ClientError;
+use crate::group::Group;
+use crate::navigation::Link;
+use crate::outbox::QueuedMessage;
+use common::types::{ChatRequest, Command, Event, Message, WebRequest};
+use std::any::Any;
+use std::collections::HashMap;
+use uuid::Uuid;
+use wg_internal::network::NodeId;
+
+/// Client-side commands that extend `common::types::ChatCommand`.
+#[derive(Debug, Clone)]
+pub enum ChatClientCommand {
+    /// Register with a chat server, or with every known chat server when `None`.
+    Register(Option<NodeId>),
+    /// Leave a chat server, or every chat server we are registered with when `None`.
+    Unregister(Option<NodeId>),
+    /// Drives request timers. The client ticks itself every `TICK_INTERVAL`,
+    /// so the controller only sends it to check them right away.
+    Tick,
+    /// Marks every message received from a client as read, sending read receipts
+    /// when enabled.
+    MarkRead(NodeId),
+    /// Requests the chat history with message ids and statuses.
+    GetHistoryWithStatus,
+    /// Creates a group with the given registered clients.
+    CreateGroup {
+        name: String,
+        members: Vec<NodeId>,
+    },
+    InviteToGroup {
+        group_id: Uuid,
+        members: Vec<NodeId>,
+    },
+    /// Sends a message to every member of a group.
+    SendGroupMessage {
+        group_id: Uuid,
+        text: String,
+    },
+    GetGroupHistory(Uuid),
+    /// Requests the messages waiting for a server that knows their recipient.
+    GetOutbox,
+    /// Drops every queued copy of a message, which is marked as failed.
+    CancelQueued(Uuid),
+}
+
+/// Client-side events that extend `common::types::ChatEvent`.
+#[derive(Debug, Clone)]
+pub enum ChatClientEvent {
+    RegistrationFailed {
+        notification_from: NodeId,
+        server: NodeId,
+        reason: String,
+    },
+    Unregistered {
+        notification_from: NodeId,
+        server: NodeId,
+    },
+    MessageRefused {
+        notification_from: NodeId,
+        to: NodeId,
+        reason: String,
+    },
+    RequestTimedOut {
+        notification_from: NodeId,
+        server: NodeId,
+        request: ChatRequest,
+    },
+    /// For a group message, `peer` is the member whose copy settled it.
+    MessageStatusChanged {
+        notification_from: NodeId,
+        peer: NodeId,
+        id: Uuid,
+        status: MessageStatus,
+    },
+    HistoryWithStatus {
+        notification_from: NodeId,
+        history: HashMap<NodeId, Vec<ChatEntry>>,
+    },
+    /// Sent both when the client creates a group and when it is invited to one.
+    GroupJoined {
+        notification_from: NodeId,
+        group: Group,
+    },
+    GroupMessageReceived {
+        notification_from: NodeId,
+        group_id: Uuid,
+        msg: Message,
+    },
+    GroupHistory {
+        notification_from: NodeId,
+        group_id: Uuid,
+        history: Vec<ChatEntry>,
+    },
+    GroupError {
+        notification_from: NodeId,
+        group_id: Uuid,
+        reason: String,
+    },
+    /// A chat server started listing a client.
+    ClientJoined {
+        notification_from: NodeId,
+        server: NodeId,
+        client: NodeId,
+    },
+    /// A chat server stopped listing a client, or its listing expired.
+    ClientLeft {
+        notification_from: NodeId,
+        server: NodeId,
+        client: NodeId,
+    },
+    /// A chat server stopped answering and was forgotten, the messages in
+    /// flight through it being sent again through the others.
+    ServerLost {
+        notification_from: NodeId,
+        server: NodeId,
+    },
+    /// A queued message waited longer than the outbox allows and was dropped.
+    MessageExpired {
+        notification_from: NodeId,
+        to: NodeId,
+        id: Uuid,
+    },
+    /// Answers `GetOutbox` and `CancelQueued`.
+    Outbox {
+        notification_from: NodeId,
+        messages: Vec<QueuedMessage>,
+    },
+}
+
+/// Client-side commands that extend `common::types::WebCommand`.
+#[derive(Debug, Clone)]
+pub enum WebBrowserCommand {
+    /// Drives request timers. The client ticks itself every `TICK_INTERVAL`,
+    /// so the controller only sends it to check them right away.
+    Tick,
+    GetCacheStats,
+    /// Drops every cached file, answered with the emptied cache stats.
+    FlushCache,
+    /// Searches the titles listed by every known text server.
+    SearchTitles(String),
+    /// Opens a text file as the new current page.
+    Visit(Uuid),
+    Back,
+    Forward,
+    /// Opens the link with this index on the current page.
+    FollowLink(usize),
+    GetNavigation,
+    /// Bookmarks a text file under its listed or cached title.
+    AddBookmark(Uuid),
+    RemoveBookmark(Uuid),
+    GetBookmarks,
+    /// Lists the media a media server is known to hold.
+    GetMediaCatalog(NodeId),
+    /// Keeps a text file and its media cached, downloading it if needed.
+    Pin(Uuid),
+    Unpin(Uuid),
+}
+
+/// Client-side events that extend `common::types::WebEvent`.
+#[derive(Debug, Clone)]
+pub enum WebBrowserEvent {
+    RequestTimedOut {
+        notification_from: NodeId,
+        server: NodeId,
+        request: WebRequest,
+    },
+    CacheStats {
+        notification_from: NodeId,
+        stats: CacheStats,
+    },
+    /// A media of a file being downloaded arrived or could not be fetched.
+    MediaProgress {
+        notification_from: NodeId,
+        file_id: Uuid,
+        received: usize,
+        total: usize,
+    },
+    /// Navigation state, sent after every move and on `GetNavigation`.
+    Navigation {
+        notification_from: NodeId,
+        current: Option<Uuid>,
+        back: Vec<Uuid>,
+        forward: Vec<Uuid>,
+        /// Links of the current page, empty until it is cached.
+        links: Vec<Link>,
+    },
+    SearchResults {
+        notification_from: NodeId,
+        query: String,
+        results: Vec<CatalogEntry>,
+    },
+    /// The file list of a text server changed since it was last received.
+    CatalogChanged {
+        notification_from: NodeId,
+        server: NodeId,
+        added: Vec<String>,
+        removed: Vec<String>,
+    },
+    /// The file list of a text server could not be refreshed in time.
+    CatalogStale {
+        notification_from: NodeId,
+        server: NodeId,
+    },
+    /// Every media of a downloaded file arrived or failed, `missing` lists the
+    /// failed ones; the complete `File` is sent right before.
+    FileCompleted {
+        notification_from: NodeId,
+        file_id: Uuid,
+        missing: Vec<Uuid>,
+    },
+    /// Media seen in references to `server` or received from it.
+    MediaCatalog {
+        notification_from: NodeId,
+        server: NodeId,
+        media: Vec<Uuid>,
+    },
+    /// Bookmarks and pinned files, sent after every change and on `GetBookmarks`.
+    Bookmarks {
+        notification_from: NodeId,
+        bookmarks: Vec<Bookmark>,
+        pinned: Vec<Uuid>,
+    },
+}
+
+/// Any command accepted by a client, whose resulting events are sent wrapped in
+/// a [`CorrelatedEvent`] with the same id, responses and timeouts of the network
+/// requests it caused included. A command joining a request already in flight
+/// gets the result of that request under its own id as well.
+#[derive(Debug)]
+pub struct CorrelatedCommand {
+    pub correlation_id: u64,
+    pub command: Box<dyn Command>,
+}
+
+#[derive(Debug)]
+pub struct CorrelatedEvent {
+    pub correlation_id: u64,
+    pub event: Box<dyn Event>,
+}
+
+/// A failure that made a client drop a request or a response, sent by both clients.
+#[derive(Debug)]
+pub struct ClientErrorEvent {
+    pub notification_from: NodeId,
+    pub error: ClientError,
+}
+
+impl Command for CorrelatedCommand {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+impl Event for CorrelatedEvent {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+impl Command for ChatClientCommand {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+impl Event for ChatClientEvent {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+impl Command for WebBrowserCommand {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+impl Event for WebBrowserEvent {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+impl Event for ClientErrorEvent {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
@@ -1,4 +1,19 @@
+use crate::bookmarks::{Bookmark, Bookmarks};
+use crate::cache::{CacheConfig, FileCache};
+use crate::catalog::{Catalog, CatalogConfig};
+use crate::disk_cache::DiskCache;
 use crate::errors::ClientError;
+use crate::media_fetch::{
+    FileDownload, MediaFetch, checksum as media_checksum, placeholder, ref_checksums,
+};
+use crate::media_library::MediaLibrary;
+use crate::navigation::{Link, Navigation, links};
+use crate::prefetch::{PrefetchConfig, Prefetcher};
+use crate::request_tracker::{Expiry, RequestTracker, RetryPolicy};
+use crate::ticker::{TICK_INTERVAL, with_ticks};
+use crate::types::{
+    ClientErrorEvent, CorrelatedCommand, CorrelatedEvent, WebBrowserCommand, WebBrowserEvent,
+};
 use common::{
     FragmentAssembler, Processor, RoutingHandler,
     types::{
@@ -7,15 +22,15 @@ use common::{
     },
 };
 use crossbeam_channel::{Receiver, Sender};
-use std::collections::{HashMap, HashSet, hash_map::Entry::Vacant};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 use uuid::Uuid;
 use wg_internal::{
     network::NodeId,
     packet::{NodeType, Packet},
 };
 
-type Cache = HashMap<TextFile, Vec<MediaFile>>;
-
 #[derive(Debug)]
 pub struct WebBrowser {
     id: NodeId,
@@ -24,9 +39,24 @@ pub struct WebBrowser {
     controller_send: Sender<Box<dyn Event>>,
     packet_recv: Receiver<Packet>,
     assembler: FragmentAssembler,
-    text_servers: HashMap<NodeId, Vec<String>>, // id, file_list
-    cached_files: Cache,
-    pending_request: Option<WebRequest>,
+    catalog: Catalog, // file lists of the text servers
+    cached_files: FileCache,
+    disk_cache: Option<DiskCache>,
+    unloaded: Vec<Uuid>, // stored files evicted while warming the cache
+    // file id, request waiting for a location, its correlation id and deadline
+    pending_requests: HashMap<Uuid, (WebRequest, Option<u64>, Instant)>,
+    media_servers: HashSet<NodeId>,
+    media_fetches: HashMap<Uuid, MediaFetch>, // media id, download in progress
+    downloads: HashMap<Uuid, FileDownload>,   // text file id, media still expected
+    file_waiters: HashMap<Uuid, Vec<Option<u64>>>, // commands waiting for a file in flight
+    media_waiters: HashMap<Uuid, Vec<Option<u64>>>, // commands waiting for a media in flight
+    media_library: MediaLibrary,              // media not referenced by a cached text file
+    partial_after: Option<Duration>,
+    navigation: Navigation,
+    bookmarks: Bookmarks,
+    prefetcher: Prefetcher,
+    requests: RequestTracker<WebRequest>,
+    correlation: Option<u64>, // command whose events are being sent
 }
 
 impl WebBrowser {
@@ -44,28 +74,189 @@ impl WebBrowser {
         Self {
             id,
             routing_handler,
-            controller_recv,
+            controller_recv: with_ticks(controller_recv, TICK_INTERVAL, || {
+                Box::new(WebBrowserCommand::Tick)
+            }),
             controller_send,
             packet_recv,
             assembler: FragmentAssembler::default(),
-            text_servers: HashMap::new(),
-            cached_files: HashMap::new(),
-            pending_request: None,
+            catalog: Catalog::new(CatalogConfig::default()),
+            cached_files: FileCache::new(CacheConfig::default()),
+            disk_cache: None,
+            unloaded: vec![],
+            pending_requests: HashMap::new(),
+            media_servers: HashSet::new(),
+            media_fetches: HashMap::new(),
+            downloads: HashMap::new(),
+            file_waiters: HashMap::new(),
+            media_waiters: HashMap::new(),
+            media_library: MediaLibrary::default(),
+            partial_after: None,
+            navigation: Navigation::default(),
+            bookmarks: Bookmarks::default(),
+            prefetcher: Prefetcher::new(PrefetchConfig::DISABLED),
+            requests: RequestTracker::new(RetryPolicy::default()),
+            correlation: None,
+        }
+    }
+
+    #[must_use]
+    pub fn with_cache_config(mut self, config: CacheConfig) -> Self {
+        self.cached_files.set_config(config);
+        self
+    }
+
+    /// Persists cached files in `dir`, warming the cache with the valid ones
+    /// already stored there.
+    pub fn with_disk_cache(mut self, dir: impl Into<PathBuf>) -> Result<Self, ClientError> {
+        let disk_cache = DiskCache::open(dir)?;
+        let stored = disk_cache.load()?;
+        self.disk_cache = Some(disk_cache);
+        self.warm_cache(stored);
+        Ok(self)
+    }
+
+    // caches stored files, pinned ones first so that eviction spares them; the
+    // evicted ones stay on disk until running, as pins may still be restored
+    fn warm_cache(&mut self, stored: Vec<(TextFile, Vec<MediaFile>)>) {
+        let (pinned, others): (Vec<_>, Vec<_>) = stored
+            .into_iter()
+            .partition(|(f, _)| self.cached_files.is_pinned(f.id));
+        for (text_file, media) in pinned.into_iter().chain(others) {
+            for evicted in self.cached_files.insert(text_file, media) {
+                if !self.unloaded.contains(&evicted.id) {
+                    self.unloaded.push(evicted.id);
+                }
+            }
+        }
+    }
+
+    // deletes the stored files left out of the cache once the builders are done
+    fn drop_unloaded(&mut self) {
+        let Some(disk_cache) = &self.disk_cache else {
+            return;
+        };
+        let mut result = Ok(());
+        for id in std::mem::take(&mut self.unloaded) {
+            if !self.cached_files.keys().any(|f| f.id == id) {
+                result = result.and(disk_cache.remove(id));
+            }
+        }
+        if let Err(e) = result {
+            let _ = self.report(e.into());
+        }
+    }
+
+    // mirror a cache update on disk
+    fn persist(&self, file: &TextFile, evicted: &[TextFile]) {
+        let Some(disk_cache) = &self.disk_cache else {
+            return;
+        };
+        let mut result = Ok(());
+        if let Some(media) = self.cached_files.media(file) {
+            result = disk_cache.store(file, &media);
+        }
+        for e in evicted {
+            result = result.and(disk_cache.remove(e.id));
+        }
+        if let Err(e) = result {
+            let _ = self.report(e.into());
+        }
+    }
+
+    /// Sends files whose media are still missing after `after` with placeholders
+    /// in their place, the complete file following once it arrives.
+    #[must_use]
+    pub fn with_partial_delivery(mut self, after: Duration) -> Self {
+        self.partial_after = Some(after);
+        self
+    }
+
+    /// Saves bookmarks and pins in the file at `path`, restoring the ones
+    /// already saved along with the pinned files of the disk cache.
+    pub fn with_bookmarks(mut self, path: impl Into<PathBuf>) -> Result<Self, ClientError> {
+        self.bookmarks = Bookmarks::open(path)?;
+        for id in self.bookmarks.pinned() {
+            self.cached_files.pin(*id);
+        }
+        if let Some(disk_cache) = &self.disk_cache {
+            let evicted_pins = disk_cache
+                .load()?
+                .into_iter()
+                .filter(|(f, _)| {
+                    self.cached_files.is_pinned(f.id) && !self.cached_files.contains_key(f)
+                })
+                .collect();
+            self.warm_cache(evicted_pins);
         }
+        Ok(self)
+    }
+
+    /// Number of media kept that no cached text file references.
+    #[must_use]
+    pub fn with_media_library_capacity(mut self, capacity: usize) -> Self {
+        self.media_library.set_capacity(capacity);
+        self
+    }
+
+    /// Downloads the files linked by the pages the controller opens, then the
+    /// bookmarked files and the ones linked by the most used cached pages, while
+    /// it has no request of its own in progress, within the budget of `config`.
+    #[must_use]
+    pub fn with_prefetch(mut self, config: PrefetchConfig) -> Self {
+        self.prefetcher.set_config(config);
+        self
+    }
+
+    #[must_use]
+    pub fn with_catalog_config(mut self, config: CatalogConfig) -> Self {
+        self.catalog.set_config(config);
+        self
+    }
+
+    #[must_use]
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.requests.set_policy(policy);
+        self
     }
 
     fn get_text_servers(&self) -> Vec<NodeId> {
-        self.text_servers.keys().copied().collect()
+        self.catalog.servers().collect()
     }
 
     fn get_list_files_by_id(&self, id: NodeId) -> Option<&Vec<String>> {
-        self.text_servers.get(&id)
+        self.catalog.files(id)
     }
 
-    fn set_files_list(&mut self, server_id: NodeId, list: Vec<String>) {
-        if let Vacant(e) = self.text_servers.entry(server_id) {
-            e.insert(list);
+    fn set_files_list(&mut self, server_id: NodeId, list: Vec<String>) -> bool {
+        let diff = self.catalog.update(server_id, list, Instant::now());
+        !diff.is_empty()
+            && self.try_send(WebBrowserEvent::CatalogChanged {
+                notification_from: self.id,
+                server: server_id,
+                added: diff.added,
+                removed: diff.removed,
+            })
+    }
+
+    // re-queries the file lists due for a refresh, reporting the ones gone stale
+    fn refresh_catalog(&mut self, now: Instant) -> bool {
+        for server in self.catalog.newly_stale(now) {
+            if self.try_send(WebBrowserEvent::CatalogStale {
+                notification_from: self.id,
+                server,
+            }) {
+                return true;
+            }
+        }
+        for server in self.catalog.due_for_refresh(now) {
+            if let Err(e) = self.send_tracked(&WebRequest::TextFilesListQuery, server)
+                && self.report(e)
+            {
+                return true;
+            }
         }
+        false
     }
 
     fn get_text_files(&self) -> Vec<TextFile> {
@@ -77,97 +268,518 @@ impl WebBrowser {
     }
 
     // find text_file that contains media file with id
-    fn get_text_file_by_media_id(&self, media_id: Uuid) -> Option<TextFile> {
+    fn get_text_files_by_media_id(&self, media_id: Uuid) -> Vec<TextFile> {
         self.cached_files
             .keys()
-            .find(|f| f.get_media_ids().contains(&media_id))
+            .filter(|f| f.get_media_ids().contains(&media_id))
             .cloned()
+            .collect()
     }
 
+    // one response serves every cached text file referencing the media, the
+    // controller getting it directly when it asked for it or nothing references it
     fn manage_media_file(&mut self, media: MediaFile) {
-        if let Some(file) = self.get_text_file_by_media_id(media.id) {
-            if let Some(vec) = self.cached_files.get_mut(&file) {
-                vec.push(media);
-                if file.get_media_ids().len() == vec.len() {
-                    let _ = self.controller_send.send(Box::new(WebEvent::File {
-                        notification_from: self.id,
-                        file: File::new(file, vec.clone()),
-                    }));
-                }
+        let files = self.get_text_files_by_media_id(media.id);
+        for file in &files {
+            if self.cached_files.has_media(file, media.id) {
+                continue;
             }
-        } else {
-            let _ = self.controller_send.send(Box::new(WebEvent::MediaFile {
-                notification_from: self.id,
-                file: media,
-            }));
+            if let Some(evicted) = self.cached_files.push_media(file, media.clone()) {
+                self.persist(file, &evicted);
+                self.update_download(file);
+            }
+        }
+        if files.is_empty() {
+            self.media_library.insert(media.clone());
         }
+        if self.media_waiters.contains_key(&media.id) || files.is_empty() {
+            let notification_from = self.id;
+            let _ = self.try_send_for_media(media.id, || WebEvent::MediaFile {
+                notification_from,
+                file: media.clone(),
+            });
+        }
+        self.media_waiters.remove(&media.id);
     }
 
-    fn get_files(&self) -> Vec<File> {
-        let mut vec = vec![];
-        for (text_file, media_files) in &self.cached_files {
-            vec.push(File::new(text_file.clone(), media_files.clone()));
+    // the cached media of `file` in reference order, with placeholders for the
+    // missing ones, which are also returned
+    fn with_placeholders(&self, file: &TextFile) -> (Vec<MediaFile>, Vec<Uuid>) {
+        let received = self.cached_files.media(file).unwrap_or_default();
+        let mut missing = vec![];
+        let media = file
+            .get_media_ids()
+            .into_iter()
+            .map(|id| {
+                received
+                    .iter()
+                    .find(|m| m.id == id)
+                    .cloned()
+                    .unwrap_or_else(|| {
+                        missing.push(id);
+                        placeholder(id)
+                    })
+            })
+            .collect();
+        (media, missing)
+    }
+
+    // notifies the progress of a download, completing it once no media is expected
+    fn update_download(&mut self, file: &TextFile) -> bool {
+        let total = file.get_media_ids().len();
+        let received = self.cached_files.media(file).map_or(0, |m| m.len());
+        let failed = self.downloads.get(&file.id).map_or(0, |d| d.failed.len());
+        if received + failed >= total {
+            return self.complete_download(file);
+        }
+        if self.prefetcher.is_background(file.id) {
+            return false;
+        }
+        let notification_from = self.id;
+        self.try_send_for_file(file.id, || WebBrowserEvent::MediaProgress {
+            notification_from,
+            file_id: file.id,
+            received,
+            total,
+        })
+    }
+
+    fn complete_download(&mut self, file: &TextFile) -> bool {
+        self.downloads.remove(&file.id);
+        if self.prefetcher.finish(file.id) {
+            self.pump_prefetches();
+            return false;
+        }
+        self.prefetch_links(file);
+        let (media, missing) = self.with_placeholders(file);
+        let notification_from = self.id;
+        let gone = self.try_send_for_file(file.id, || WebEvent::File {
+            notification_from,
+            file: File::new(file.clone(), media.clone()),
+        }) || self.try_send_for_file(file.id, || WebBrowserEvent::FileCompleted {
+            notification_from,
+            file_id: file.id,
+            missing: missing.clone(),
+        });
+        self.file_waiters.remove(&file.id);
+        gone
+    }
+
+    // records the command waiting for the file `id`, which gets the result of
+    // the query in flight for it under its own correlation id
+    fn wait_for_file(&mut self, id: Uuid) {
+        let waiters = self.file_waiters.entry(id).or_default();
+        if !waiters.contains(&self.correlation) {
+            waiters.push(self.correlation);
+        }
+    }
+
+    // records the command waiting for the media `id`, answered like `wait_for_file`
+    fn wait_for_media(&mut self, id: Uuid) {
+        let waiters = self.media_waiters.entry(id).or_default();
+        if !waiters.contains(&self.correlation) {
+            waiters.push(self.correlation);
         }
-        vec
     }
 
-    fn get_file(&self, id: Uuid) -> Option<File> {
-        if let Some((tf, m)) = self.cached_files.iter().find(|(f, _)| f.id == id) {
-            return Some(File::new(tf.clone(), m.clone()));
+    // sends the event built by `event` once for every command waiting for the
+    // file `id`, or once as is when none is
+    fn try_send_for_file<E: Event + 'static>(&mut self, id: Uuid, event: impl Fn() -> E) -> bool {
+        let waiters = self.file_waiters.get(&id).cloned();
+        self.try_send_to(waiters, event)
+    }
+
+    fn try_send_for_media<E: Event + 'static>(&mut self, id: Uuid, event: impl Fn() -> E) -> bool {
+        let waiters = self.media_waiters.get(&id).cloned();
+        self.try_send_to(waiters, event)
+    }
+
+    fn try_send_to<E: Event + 'static>(
+        &mut self,
+        waiters: Option<Vec<Option<u64>>>,
+        event: impl Fn() -> E,
+    ) -> bool {
+        let Some(waiters) = waiters else {
+            return self.try_send(event());
+        };
+        let correlation = self.correlation;
+        let gone = waiters.into_iter().any(|waiter| {
+            self.correlation = waiter;
+            self.try_send(event())
+        });
+        self.correlation = correlation;
+        gone
+    }
+
+    // sends, once, every download still incomplete past the partial delivery deadline
+    fn deliver_overdue_partials(&mut self, now: Instant) -> bool {
+        let Some(after) = self.partial_after else {
+            return false;
+        };
+        let overdue = self
+            .downloads
+            .iter_mut()
+            .filter(|(id, d)| {
+                !d.partial_sent
+                    && now.duration_since(d.started) >= after
+                    && !self.prefetcher.is_background(**id)
+            })
+            .map(|(id, d)| {
+                d.partial_sent = true;
+                *id
+            })
+            .collect::<Vec<_>>();
+        for id in overdue {
+            let Some(file) = self.get_text_file(id) else {
+                continue;
+            };
+            let (media, _) = self.with_placeholders(&file);
+            let notification_from = self.id;
+            if self.try_send_for_file(id, || WebEvent::File {
+                notification_from,
+                file: File::new(file.clone(), media.clone()),
+            }) {
+                return true;
+            }
         }
-        None
+        false
+    }
+
+    fn get_files(&self) -> Vec<File> {
+        self.cached_files.files()
+    }
+
+    fn get_file(&mut self, id: Uuid) -> Option<File> {
+        self.cached_files.get(id)
     }
 
     fn locate_file(&self, uuid: Uuid) -> Option<NodeId> {
-        for (server, file_list) in &self.text_servers {
-            if file_list.contains(&uuid.to_string()) {
-                return Some(*server);
+        self.catalog.locate(uuid)
+    }
+
+    // another text server listing the requested file, used when re-routing
+    fn alternative_location(&self, req: &WebRequest, current: NodeId) -> Option<NodeId> {
+        let WebRequest::FileQuery { file_id } = req else {
+            return None;
+        };
+        self.catalog
+            .alternative(Uuid::parse_str(file_id).ok()?, current)
+    }
+
+    fn send_raw(
+        &mut self,
+        req: &WebRequest,
+        dest: NodeId,
+        session_id: Option<u64>,
+    ) -> Result<(), ClientError> {
+        let serialized = serde_json::to_vec(req).map_err(|_| ClientError::SerializationError)?;
+        self.routing_handler
+            .send_message(&serialized, dest, session_id)
+            .map_err(|error| ClientError::RoutingError {
+                destination: dest,
+                error,
+            })
+    }
+
+    // the request stays tracked when sending fails, so it is retried like a lost one
+    fn send_tracked(&mut self, req: &WebRequest, dest: NodeId) -> Result<(), ClientError> {
+        let session_id = self
+            .requests
+            .track(req.clone(), dest, self.correlation, Instant::now());
+        self.send_raw(req, dest, Some(session_id))
+    }
+
+    // retries, re-routes or fails every request whose response is overdue
+    fn check_timeouts(&mut self) -> bool {
+        self.correlation = None;
+        if !self.unloaded.is_empty() {
+            self.drop_unloaded();
+        }
+        let now = Instant::now();
+        if self.deliver_overdue_partials(now)
+            || self.refresh_catalog(now)
+            || self.expire_pending_requests(now)
+        {
+            return true;
+        }
+        self.prefetch_popular();
+        for expiry in self.requests.poll(now) {
+            match expiry {
+                Expiry::Retry {
+                    session_id,
+                    request,
+                    destination,
+                } => {
+                    self.correlation = self.requests.correlation(session_id);
+                    if let Err(e) = self.send_raw(&request, destination, Some(session_id))
+                        && self.report(e)
+                    {
+                        return true;
+                    }
+                }
+                Expiry::Reroute {
+                    session_id,
+                    request,
+                    destination,
+                } => {
+                    self.correlation = self.requests.correlation(session_id);
+                    let dest = match &request {
+                        WebRequest::MediaQuery { media_id } => self.media_reroute(media_id),
+                        _ => self.alternative_location(&request, destination),
+                    }
+                    .unwrap_or(destination);
+                    self.requests.retarget(session_id, dest);
+                    if let Err(e) = self.send_raw(&request, dest, Some(session_id))
+                        && self.report(e)
+                    {
+                        return true;
+                    }
+                }
+                Expiry::TimedOut {
+                    request,
+                    destination,
+                    correlation,
+                } => {
+                    self.correlation = correlation;
+                    // a media download only fails once every location was tried
+                    if let WebRequest::MediaQuery { media_id } = &request
+                        && let Ok(media_id) = Uuid::parse_str(media_id)
+                        && self.media_fetches.contains_key(&media_id)
+                    {
+                        if self.fetch_media_elsewhere(media_id) {
+                            return true;
+                        }
+                        continue;
+                    }
+                    let file_id = match &request {
+                        WebRequest::FileQuery { file_id } => Uuid::parse_str(file_id).ok(),
+                        _ => None,
+                    };
+                    if file_id.is_some_and(|id| self.prefetcher.finish(id)) {
+                        continue;
+                    }
+                    let notification_from = self.id;
+                    let timed_out = || WebBrowserEvent::RequestTimedOut {
+                        notification_from,
+                        server: destination,
+                        request: request.clone(),
+                    };
+                    let gone = match file_id {
+                        Some(id) => {
+                            let gone = self.try_send_for_file(id, timed_out);
+                            self.file_waiters.remove(&id);
+                            gone
+                        }
+                        None => self.try_send(timed_out()),
+                    };
+                    if gone {
+                        return true;
+                    }
+                    // a text server that never lists its files stops holding up
+                    // the requests waiting for every list
+                    if matches!(request, WebRequest::TextFilesListQuery)
+                        && self.catalog.forget_unlisted(destination)
+                    {
+                        self.retry_pending_requests();
+                    }
+                }
             }
         }
-        None
+        self.correlation = None;
+        false
     }
 
-    fn manage_text_file(&mut self, file: TextFile, session_id: u64) {
-        for r in &file.get_refs() {
-            if let Ok(req) = serde_json::to_vec(&WebRequest::MediaQuery {
-                media_id: r.id.to_string(),
-            }) {
-                let _ = self
-                    .routing_handler
-                    .send_message(&req, r.get_location(), Some(session_id));
+    // stop tracking the queries answered by a response for `id`
+    fn resolve_requests_for(&mut self, id: &str) {
+        self.resolve_answered(|r, _| match r {
+            WebRequest::FileQuery { file_id } => file_id == id,
+            WebRequest::MediaQuery { media_id } => media_id == id,
+            _ => false,
+        });
+    }
+
+    // stops tracking the requests a response answers, correlating what follows
+    // with the command that sent them rather than with the response's session
+    fn resolve_answered(&mut self, pred: impl Fn(&WebRequest, NodeId) -> bool) {
+        self.correlation = self
+            .requests
+            .resolve_correlated_by(pred)
+            .into_iter()
+            .find_map(|(_, correlation)| correlation);
+    }
+
+    // joins the query already in flight for the same media, if any
+    fn fetch_media(&mut self, media_id: Uuid, location: NodeId, checksum: Option<u64>) -> bool {
+        if let Some(fetch) = self.media_fetches.get_mut(&media_id) {
+            fetch.checksum = fetch.checksum.or(checksum);
+            return false;
+        }
+        self.media_fetches
+            .insert(media_id, MediaFetch::new(location, checksum));
+        let req = WebRequest::MediaQuery {
+            media_id: media_id.to_string(),
+        };
+        match self.send_tracked(&req, location) {
+            Ok(()) => false,
+            Err(e) => self.report(e),
+        }
+    }
+
+    // the servers that referenced or served the media first, then every other
+    // media server discovered, each sorted so that fallbacks are tried in a stable order
+    fn media_fallbacks(&self, media_id: Uuid) -> Vec<NodeId> {
+        let mut fallbacks = self.media_library.holders(media_id);
+        let mut discovered = self
+            .media_servers
+            .iter()
+            .filter(|s| !fallbacks.contains(s))
+            .copied()
+            .collect::<Vec<_>>();
+        discovered.sort_unstable();
+        fallbacks.extend(discovered);
+        fallbacks
+    }
+
+    // next location for a media query being re-routed
+    fn media_reroute(&mut self, media_id: &str) -> Option<NodeId> {
+        let media_id = Uuid::parse_str(media_id).ok()?;
+        let fallbacks = self.media_fallbacks(media_id);
+        self.media_fetches
+            .get_mut(&media_id)?
+            .next_location(fallbacks)
+    }
+
+    // asks the next media server for a media its previous location could not serve,
+    // waiting for a media server to be discovered when none is known yet
+    fn fetch_media_elsewhere(&mut self, media_id: Uuid) -> bool {
+        let fallbacks = self.media_fallbacks(media_id);
+        let Some(fetch) = self.media_fetches.get_mut(&media_id) else {
+            return false;
+        };
+        if let Some(dest) = fetch.next_location(fallbacks.iter().copied()) {
+            let req = WebRequest::MediaQuery {
+                media_id: media_id.to_string(),
+            };
+            return match self.send_tracked(&req, dest) {
+                Ok(()) => false,
+                Err(e) => self.report(e),
+            };
+        }
+        if self.media_servers.is_empty() {
+            fetch.parked = true;
+            return self.broadcast();
+        }
+        self.media_fetches.remove(&media_id);
+        let notification_from = self.id;
+        let gone = self.try_send_for_media(media_id, || ClientErrorEvent {
+            notification_from,
+            error: ClientError::FileNotFound(media_id),
+        });
+        self.media_waiters.remove(&media_id);
+        if gone {
+            return true;
+        }
+        for file in self.get_text_files_by_media_id(media_id) {
+            self.downloads
+                .entry(file.id)
+                .or_insert_with(|| FileDownload::new(Instant::now()))
+                .failed
+                .insert(media_id);
+            if self.update_download(&file) {
+                return true;
             }
         }
-        if file.get_refs().is_empty() {
-            let _ = self.controller_send.send(Box::new(WebEvent::File {
-                notification_from: self.id,
-                file: File::new(file.clone(), vec![]),
-            }));
+        false
+    }
+
+    fn resume_parked_fetches(&mut self) {
+        let parked = self
+            .media_fetches
+            .iter_mut()
+            .filter(|(_, f)| f.parked)
+            .map(|(id, f)| {
+                f.parked = false;
+                *id
+            })
+            .collect::<Vec<_>>();
+        for media_id in parked {
+            self.fetch_media_elsewhere(media_id);
+        }
+    }
+
+    fn manage_text_file(&mut self, file: TextFile, checksums: &HashMap<Uuid, u64>) {
+        // a duplicate or late copy would release the media cached or on their way
+        if self.downloads.contains_key(&file.id) || self.get_text_file(file.id).is_some() {
+            return;
+        }
+        if self.prefetcher.is_cancelled(file.id) {
+            self.prefetcher.finish(file.id);
+            return;
         }
-        let _ = self.cached_files.insert(file, vec![]);
+        let evicted = self.cached_files.insert(file.clone(), vec![]);
+        self.persist(&file, &evicted);
+        let refs = file.get_refs();
+        if refs.is_empty() {
+            self.complete_download(&file);
+            return;
+        }
+        self.downloads
+            .insert(file.id, FileDownload::new(Instant::now()));
+        let mut fetching = false;
+        for r in &refs {
+            self.media_library.locate(r.get_location(), r.id);
+            let checksum = checksums.get(&r.id).copied();
+            // a media already stored for another file is shared and one stored on
+            // its own moves to the cache, neither being fetched again
+            let stored = self
+                .cached_files
+                .media_by_id(r.id)
+                .cloned()
+                .or_else(|| self.media_library.take(r.id))
+                .filter(|m| checksum.is_none_or(|c| c == media_checksum(m)));
+            if let Some(media) = stored {
+                if let Some(evicted) = self.cached_files.push_media(&file, media) {
+                    self.persist(&file, &evicted);
+                }
+                continue;
+            }
+            fetching = true;
+            self.fetch_media(r.id, r.get_location(), checksum);
+        }
+        if !fetching {
+            self.update_download(&file);
+        }
+    }
+
+    // wraps the event when it results from a `CorrelatedCommand`
+    fn try_send<E: Event + 'static>(&self, event: E) -> bool {
+        let event: Box<dyn Event> = match self.correlation {
+            Some(correlation_id) => Box::new(CorrelatedEvent {
+                correlation_id,
+                event: Box::new(event),
+            }),
+            None => Box::new(event),
+        };
+        self.controller_send.send(event).is_err()
     }
 
-    fn try_send(&self, event: WebEvent) -> bool {
-        self.controller_send.send(Box::new(event)).is_err()
+    // reports a failure that would otherwise be dropped, true when the controller is gone
+    fn report(&self, error: ClientError) -> bool {
+        self.try_send(ClientErrorEvent {
+            notification_from: self.id,
+            error,
+        })
     }
 
-    // TODO: Create Custom errors (WebBrowserError) of type (NoLocation, SerializeError,
-    // UuidParaseError)
     fn forward_request(&mut self, req: &WebRequest) -> Result<(), ClientError> {
-        if let Ok(serialized) = serde_json::to_vec(req) {
-            if let Some(uuid) = req.get_file_id() {
-                if let Ok(uuid) = Uuid::parse_str(&uuid) {
-                    if let Some(location) = self.locate_file(uuid) {
-                        let _ = self
-                            .routing_handler
-                            .send_message(&serialized, location, None);
-                        return Ok(());
-                    }
-                    return Err(ClientError::NoLocationError);
+        if let Some(uuid) = req.get_file_id() {
+            if let Ok(uuid) = Uuid::parse_str(&uuid) {
+                if let Some(location) = self.locate_file(uuid) {
+                    return self.send_tracked(req, location);
                 }
-                return Err(ClientError::UuidParseError);
+                return Err(ClientError::NoLocationError);
             }
-        } else {
-            return Err(ClientError::SerializationError);
+            return Err(ClientError::UuidParseError);
         }
 
         Ok(())
@@ -181,40 +793,36 @@ impl WebBrowser {
         })
     }
 
-    fn broadcast(&mut self) {
+    fn broadcast(&mut self) -> bool {
         if let Some(servers) = self.routing_handler.get_servers() {
             for s in servers {
-                if let Ok(req) = serde_json::to_vec(&WebRequest::ServerTypeQuery) {
-                    let _ = self.routing_handler.send_message(&req, s, None);
+                if let Err(e) = self.send_raw(&WebRequest::ServerTypeQuery, s, None)
+                    && self.report(e)
+                {
+                    return true;
                 }
             }
         }
+        false
     }
 
     fn handle_get_file(&mut self, uuid: Uuid) -> bool {
         if let Some(file) = self.get_file(uuid) {
-            return self.try_send(WebEvent::File {
+            // a prefetched file still downloading media is delivered again once complete
+            if self.prefetcher.promote(uuid) {
+                self.wait_for_file(uuid);
+            }
+            let text_file = file.text_file.clone();
+            if self.try_send(WebEvent::File {
                 notification_from: self.id,
                 file,
-            });
-        }
-        match self.forward_request(&WebRequest::FileQuery {
-            file_id: uuid.to_string(),
-        }) {
-            Ok(()) => return false,
-            Err(ClientError::NoLocationError) => {
-                self.broadcast();
-                self.pending_request = Some(WebRequest::FileQuery {
-                    file_id: uuid.to_string(),
-                });
-            }
-            Err(e) => {
-                eprintln!("Error forwarding request: {e}");
-                return false;
+            }) {
+                return true;
             }
+            self.prefetch_links(&text_file);
+            return false;
         }
-
-        false
+        self.request_file(uuid)
     }
 
     fn handle_get_text_files(&self) -> bool {
@@ -232,160 +840,585 @@ impl WebBrowser {
                 file: file.clone(),
             });
         }
-        match self.forward_request(&WebRequest::FileQuery {
+        self.request_file(uuid)
+    }
+
+    fn request_file(&mut self, uuid: Uuid) -> bool {
+        self.wait_for_file(uuid);
+        if self.prefetcher.promote(uuid) || self.file_in_flight(uuid) {
+            return false;
+        }
+        self.cancel_prefetches();
+        let req = WebRequest::FileQuery {
             file_id: uuid.to_string(),
-        }) {
-            Ok(()) => return false,
+        };
+        match self.forward_request(&req) {
+            Ok(()) => false,
             Err(ClientError::NoLocationError) => {
-                self.broadcast();
-                self.pending_request = Some(WebRequest::FileQuery {
-                    file_id: uuid.to_string(),
-                });
-            }
-            Err(e) => {
-                eprintln!("Error forwarding request: {e}");
-                return false;
+                let deadline = Instant::now() + self.requests.policy().give_up_after();
+                self.pending_requests
+                    .insert(uuid, (req, self.correlation, deadline));
+                self.broadcast()
             }
+            Err(e) => self.report(e),
         }
-        false
     }
 
-    fn handle_get_media_files(&self) -> bool {
-        let media: HashSet<_> = self.cached_files.values().flatten().cloned().collect();
-        self.try_send(WebEvent::MediaFiles {
-            notification_from: self.id,
-            files: media.into_iter().collect(),
-        })
+    // a query for `uuid` already waits for a location or a response, which
+    // satisfies every command asking for the file
+    fn file_in_flight(&self, uuid: Uuid) -> bool {
+        let file_id = uuid.to_string();
+        self.pending_requests.contains_key(&uuid)
+            || self
+                .requests
+                .any(|r, _| matches!(r, WebRequest::FileQuery { file_id: id } if *id == file_id))
     }
 
-    fn handle_get_media_file(&mut self, media_id: Uuid, location: NodeId) -> bool {
-        for v in self.cached_files.values() {
-            if let Some(media) = v.iter().find(|m| m.id == media_id) {
-                return self.try_send(WebEvent::MediaFile {
-                    notification_from: self.id,
-                    file: media.clone(),
-                });
+    // retry every pending request against the known file lists, failing the ones
+    // that no text server can serve once all of them have answered
+    fn retry_pending_requests(&mut self) {
+        let all_listed = self.catalog.all_listed();
+        let pending = self.pending_requests.drain().collect::<Vec<_>>();
+        let correlation = self.correlation;
+        for (uuid, (req, request_correlation, deadline)) in pending {
+            self.correlation = request_correlation;
+            match self.forward_request(&req) {
+                Ok(()) => {}
+                Err(ClientError::NoLocationError) if all_listed => {
+                    let notification_from = self.id;
+                    let _ = self.try_send_for_file(uuid, || WebEvent::FileNotFound {
+                        notification_from,
+                        uuid,
+                    });
+                    self.file_waiters.remove(&uuid);
+                }
+                Err(ClientError::NoLocationError) => {
+                    self.pending_requests
+                        .insert(uuid, (req, request_correlation, deadline));
+                }
+                Err(e) => {
+                    let _ = self.report(e);
+                }
             }
         }
-        if let Ok(req) = serde_json::to_vec(&WebRequest::MediaQuery {
-            media_id: media_id.to_string(),
-        }) {
-            let _ = self.routing_handler.send_message(&req, location, None);
+        self.correlation = correlation;
+    }
+
+    // fails the requests that waited for a location as long as a request waits
+    // for its response
+    fn expire_pending_requests(&mut self, now: Instant) -> bool {
+        let expired = self
+            .pending_requests
+            .iter()
+            .filter(|(_, (_, _, deadline))| *deadline <= now)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        for uuid in expired {
+            self.pending_requests.remove(&uuid);
+            let notification_from = self.id;
+            let gone = self.try_send_for_file(uuid, || ClientErrorEvent {
+                notification_from,
+                error: ClientError::NoLocationError,
+            });
+            self.file_waiters.remove(&uuid);
+            if gone {
+                return true;
+            }
         }
         false
     }
-}
 
-impl Processor for WebBrowser {
-    fn controller_recv(&self) -> &Receiver<Box<dyn Command>> {
-        &self.controller_recv
+    fn handle_get_cache_stats(&self) -> bool {
+        self.try_send(WebBrowserEvent::CacheStats {
+            notification_from: self.id,
+            stats: self.cached_files.stats(),
+        })
     }
 
-    fn packet_recv(&self) -> &Receiver<Packet> {
-        &self.packet_recv
+    fn handle_search_titles(&self, query: &str) -> bool {
+        self.try_send(WebBrowserEvent::SearchResults {
+            notification_from: self.id,
+            query: query.to_string(),
+            results: self.catalog.search(query),
+        })
     }
 
-    fn assembler(&mut self) -> &mut FragmentAssembler {
-        &mut self.assembler
+    fn current_links(&self) -> Vec<Link> {
+        self.navigation
+            .current()
+            .and_then(|page| self.get_text_file(page))
+            .map(|file| links(&file.content))
+            .unwrap_or_default()
     }
 
-    fn routing_handler(&mut self) -> &mut RoutingHandler {
-        &mut self.routing_handler
+    fn handle_get_navigation(&self) -> bool {
+        self.try_send(WebBrowserEvent::Navigation {
+            notification_from: self.id,
+            current: self.navigation.current(),
+            back: self.navigation.back_history().to_vec(),
+            forward: self.navigation.forward_history(),
+            links: self.current_links(),
+        })
     }
 
-    fn handle_command(&mut self, cmd: Box<dyn Command>) -> bool {
-        let cmd = cmd.into_any();
-        if let Some(cmd) = cmd.downcast_ref::<WebCommand>() {
-            match cmd {
-                WebCommand::GetCachedFiles => self.handle_get_cached_files(),
-                WebCommand::GetFile(uuid) => self.handle_get_file(*uuid),
-                WebCommand::GetTextFiles => self.handle_get_text_files(),
-                WebCommand::GetTextFile(uuid) => self.handle_get_text_file(*uuid),
-                WebCommand::GetMediaFiles => self.handle_get_media_files(),
-                WebCommand::GetMediaFile { media_id, location } => {
-                    self.handle_get_media_file(*media_id, *location)
-                }
-                _ => {
-                    eprintln!("Unsupported command: {cmd:?}");
-                    false
-                }
-            }
-        } else if let Some(cmd) = cmd.downcast_ref::<NodeCommand>() {
-            match cmd {
-                NodeCommand::AddSender(node_id, sender) => {
-                    self.routing_handler.add_neighbor(*node_id, sender.clone());
-                    return false;
-                }
-                NodeCommand::RemoveSender(node_id) => {
-                    self.routing_handler.remove_neighbor(*node_id);
-                    return false;
-                }
-                NodeCommand::Shutdown => return true,
-            }
-        } else {
-            false
+    // reports the move, then opens the page like `GetFile`
+    fn open_page(&mut self, page: Option<Uuid>) -> bool {
+        if self.handle_get_navigation() {
+            return true;
         }
+        page.is_some_and(|page| self.handle_get_file(page))
     }
 
-    fn handle_msg(&mut self, msg: Vec<u8>, from: NodeId, session_id: u64) {
-        let _ = self
-            .controller_send
-            .send(Box::new(NodeEvent::MessageReceived {
-                notification_from: self.id,
-                from,
-            }));
-        if let Ok(msg) = serde_json::from_slice::<WebResponse>(&msg) {
-            match msg {
-                WebResponse::ServerType { server_type } => {
-                    if matches!(server_type, ServerType::TextServer) {
-                        self.text_servers.insert(from, vec![]);
-                        let _ = self.forward_request(&WebRequest::TextFilesListQuery);
-                    }
-                }
-                WebResponse::TextFilesList { files } => {
-                    self.set_files_list(from, files);
-                    if let Some(req) = self.pending_request.take() {
-                        match self.forward_request(&req) {
-                            Ok(()) => {}
-                            Err(ClientError::NoLocationError) => {
-                                self.pending_request = Some(req);
-                            }
-                            Err(e) => eprintln!("Error forwarding request: {e}"),
-                        }
-                    }
-                }
-                WebResponse::TextFile { file_data } => {
-                    if let Ok(file) = serde_json::from_slice::<TextFile>(&file_data) {
-                        self.manage_text_file(file, session_id);
-                    }
-                }
-                WebResponse::MediaFile { media_data } => {
-                    if let Ok(mediafile) = serde_json::from_slice::<MediaFile>(&media_data) {
-                        // check if all media files are present, if yes send to controller
-                        self.manage_media_file(mediafile);
-                    }
-                }
-                WebResponse::ErrorFileNotFound(uuid) => {
-                    let _ = self.controller_send.send(Box::new(WebEvent::FileNotFound {
-                        notification_from: self.id,
-                        uuid,
-                    }));
-                }
-                WebResponse::BadUuid(uuid) => {
-                    let _ = self.controller_send.send(Box::new(WebEvent::BadUuid {
-                        notification_from: self.id,
-                        from,
-                        uuid,
-                    }));
-                }
-            }
+    fn handle_visit(&mut self, page: Uuid) -> bool {
+        self.navigation.visit(page);
+        self.open_page(Some(page))
+    }
+
+    fn handle_follow_link(&mut self, index: usize) -> bool {
+        match self.current_links().get(index) {
+            Some(link) => self.handle_visit(link.target),
+            None => self.report(ClientError::NoSuchLink(index)),
         }
     }
-}
 
-#[cfg(test)]
+    fn handle_get_bookmarks(&self) -> bool {
+        self.try_send(WebBrowserEvent::Bookmarks {
+            notification_from: self.id,
+            bookmarks: self.bookmarks.list().to_vec(),
+            pinned: self.bookmarks.pinned().to_vec(),
+        })
+    }
+
+    fn handle_add_bookmark(&mut self, id: Uuid) -> bool {
+        let title = match self.catalog.entry(id) {
+            Some(entry) => entry.title.clone(),
+            None => self.get_text_file(id).map(|f| f.title).unwrap_or_default(),
+        };
+        if let Err(e) = self.bookmarks.add(Bookmark { id, title })
+            && self.report(e.into())
+        {
+            return true;
+        }
+        self.handle_get_bookmarks()
+    }
+
+    fn handle_remove_bookmark(&mut self, id: Uuid) -> bool {
+        if let Err(e) = self.bookmarks.remove(id)
+            && self.report(e.into())
+        {
+            return true;
+        }
+        self.handle_get_bookmarks()
+    }
+
+    // a pinned file not cached yet is downloaded so that it is available offline
+    fn handle_pin(&mut self, id: Uuid) -> bool {
+        self.cached_files.pin(id);
+        if let Err(e) = self.bookmarks.pin(id)
+            && self.report(e.into())
+        {
+            return true;
+        }
+        if self.handle_get_bookmarks() {
+            return true;
+        }
+        self.get_text_file(id).is_none() && self.request_file(id)
+    }
+
+    fn handle_unpin(&mut self, id: Uuid) -> bool {
+        let evicted = self.cached_files.unpin(id);
+        let mut result = self.bookmarks.unpin(id);
+        if let Some(disk_cache) = &self.disk_cache {
+            for e in &evicted {
+                result = result.and(disk_cache.remove(e.id));
+            }
+        }
+        if let Err(e) = result
+            && self.report(e.into())
+        {
+            return true;
+        }
+        self.handle_get_bookmarks()
+    }
+
+    // pinned files survive the flush, on disk as well
+    fn handle_flush_cache(&mut self) -> bool {
+        self.cached_files.flush();
+        self.media_library.clear();
+        self.downloads.clear();
+        if let Some(Err(e)) = self.disk_cache.as_ref().map(DiskCache::clear)
+            && self.report(e.into())
+        {
+            return true;
+        }
+        for file in self.cached_files.keys() {
+            self.persist(file, &[]);
+        }
+        self.handle_get_cache_stats()
+    }
+
+    // whether no request of the controller is waiting for a response
+    fn is_idle(&self) -> bool {
+        self.pending_requests.is_empty()
+            && !self.requests.any(|r, _| {
+                let id = match r {
+                    WebRequest::FileQuery { file_id } => Uuid::parse_str(file_id).ok(),
+                    WebRequest::MediaQuery { media_id } => Uuid::parse_str(media_id)
+                        .ok()
+                        .map(|m| self.get_text_files_by_media_id(m))
+                        .and_then(|files| files.first().map(|f| f.id)),
+                    _ => return false,
+                };
+                id.is_none_or(|id| !self.prefetcher.is_background(id))
+            })
+    }
+
+    // queues the linked files that are not cached yet
+    fn prefetch_links(&mut self, file: &TextFile) {
+        for link in links(&file.content) {
+            if self.get_text_file(link.target).is_none() {
+                self.prefetcher.enqueue(link.target);
+            }
+        }
+        self.pump_prefetches();
+    }
+
+    // once the links are exhausted, queues the bookmarked files that are not
+    // cached, then the ones linked by the most used cached pages
+    fn prefetch_popular(&mut self) {
+        if self.prefetcher.is_starved() && self.is_idle() {
+            let bookmarked = self.bookmarks.list().iter().map(|b| b.id);
+            let linked = self
+                .cached_files
+                .most_used()
+                .into_iter()
+                .flat_map(|f| links(&f.content))
+                .map(|l| l.target);
+            let candidates = bookmarked.chain(linked).collect::<Vec<_>>();
+            for id in candidates {
+                if self.get_text_file(id).is_none() && !self.file_in_flight(id) {
+                    self.prefetcher.enqueue_popular(id);
+                }
+            }
+        }
+        self.pump_prefetches();
+    }
+
+    // starts queued prefetches within the budget, only while the controller waits
+    // for nothing; failures are dropped silently as nobody asked for these files
+    fn pump_prefetches(&mut self) {
+        let correlation = self.correlation.take();
+        while self.is_idle()
+            && let Some(id) = self.prefetcher.start_next()
+        {
+            let req = WebRequest::FileQuery {
+                file_id: id.to_string(),
+            };
+            if self.get_text_file(id).is_some() || self.forward_request(&req).is_err() {
+                self.prefetcher.finish(id);
+            }
+        }
+        self.correlation = correlation;
+    }
+
+    // frees the routes for a request of the controller; a prefetched file still
+    // missing media is dropped, its media kept aside, so that a later request
+    // fetches it whole
+    fn cancel_prefetches(&mut self) {
+        let cancelled = self.prefetcher.cancel();
+        let mut media_ids = HashSet::new();
+        for id in &cancelled {
+            if self.downloads.remove(id).is_none() {
+                continue;
+            }
+            self.prefetcher.finish(*id);
+            let Some(file) = self.get_text_file(*id) else {
+                continue;
+            };
+            media_ids.extend(file.get_media_ids());
+            let received = self.cached_files.media(&file).unwrap_or_default();
+            self.cached_files.remove(&file);
+            for media in received {
+                if self.cached_files.media_by_id(media.id).is_none() {
+                    self.media_library.insert(media);
+                }
+            }
+            if let Some(Err(e)) = self.disk_cache.as_ref().map(|d| d.remove(*id)) {
+                let _ = self.report(e.into());
+            }
+        }
+        // media still expected by another download or asked for by the controller
+        let needed = self
+            .downloads
+            .keys()
+            .filter_map(|id| self.get_text_file(*id))
+            .flat_map(|f| f.get_media_ids())
+            .collect::<HashSet<_>>();
+        media_ids.retain(|id| {
+            !needed.contains(id)
+                && self.media_fetches.contains_key(id)
+                && !self.media_waiters.contains_key(id)
+        });
+        for id in &media_ids {
+            self.media_fetches.remove(id);
+        }
+        let ids = cancelled
+            .iter()
+            .chain(&media_ids)
+            .map(Uuid::to_string)
+            .collect::<HashSet<_>>();
+        self.requests.resolve_by(|r, _| match r {
+            WebRequest::FileQuery { file_id } => ids.contains(file_id),
+            WebRequest::MediaQuery { media_id } => ids.contains(media_id),
+            _ => false,
+        });
+    }
+
+    fn handle_get_media_files(&self) -> bool {
+        let mut files = self.cached_files.media_files().cloned().collect::<Vec<_>>();
+        for media in self.media_library.media_files() {
+            if self.cached_files.media_by_id(media.id).is_none() {
+                files.push(media.clone());
+            }
+        }
+        self.try_send(WebEvent::MediaFiles {
+            notification_from: self.id,
+            files,
+        })
+    }
+
+    fn handle_get_media_catalog(&self, server: NodeId) -> bool {
+        self.try_send(WebBrowserEvent::MediaCatalog {
+            notification_from: self.id,
+            server,
+            media: self.media_library.catalog(server),
+        })
+    }
+
+    fn handle_get_media_file(&mut self, media_id: Uuid, location: NodeId) -> bool {
+        let cached = self
+            .cached_files
+            .media_by_id(media_id)
+            .cloned()
+            .or_else(|| self.media_library.get(media_id));
+        if let Some(media) = cached {
+            return self.try_send(WebEvent::MediaFile {
+                notification_from: self.id,
+                file: media,
+            });
+        }
+        self.cancel_prefetches();
+        self.wait_for_media(media_id);
+        self.fetch_media(media_id, location, None)
+    }
+
+    fn handle_response(&mut self, response: WebResponse, from: NodeId) {
+        match response {
+            WebResponse::ServerType { server_type } => match server_type {
+                ServerType::TextServer => {
+                    self.catalog.discover(from);
+                    if let Err(e) = self.send_tracked(&WebRequest::TextFilesListQuery, from) {
+                        let _ = self.report(e);
+                    }
+                }
+                ServerType::MediaServer if self.media_servers.insert(from) => {
+                    self.resume_parked_fetches();
+                }
+                _ => {}
+            },
+            WebResponse::TextFilesList { files } => {
+                self.resolve_answered(|r, dest| {
+                    matches!(r, WebRequest::TextFilesListQuery) && dest == from
+                });
+                self.set_files_list(from, files);
+                self.retry_pending_requests();
+            }
+            WebResponse::TextFile { file_data } => {
+                match serde_json::from_slice::<TextFile>(&file_data) {
+                    Ok(file) => {
+                        self.resolve_requests_for(&file.id.to_string());
+                        self.manage_text_file(file, &ref_checksums(&file_data));
+                    }
+                    Err(e) => {
+                        let _ = self.report(ClientError::DeserializationError {
+                            from,
+                            reason: e.to_string(),
+                        });
+                    }
+                }
+            }
+            WebResponse::MediaFile { media_data } => {
+                match serde_json::from_slice::<MediaFile>(&media_data) {
+                    Ok(mediafile) => {
+                        self.resolve_requests_for(&mediafile.id.to_string());
+                        let Some(fetch) = self.media_fetches.get(&mediafile.id) else {
+                            // a copy or a cancelled prefetch, kept aside quietly
+                            self.media_library.locate(from, mediafile.id);
+                            if self.cached_files.media_by_id(mediafile.id).is_none() {
+                                self.media_library.insert(mediafile);
+                            }
+                            return;
+                        };
+                        if !fetch.verify(&mediafile) {
+                            let _ = self.report(ClientError::ChecksumMismatch {
+                                media_id: mediafile.id,
+                                from,
+                            });
+                            self.fetch_media_elsewhere(mediafile.id);
+                        } else {
+                            self.media_library.locate(from, mediafile.id);
+                            self.media_fetches.remove(&mediafile.id);
+                            self.manage_media_file(mediafile);
+                        }
+                    }
+                    Err(e) => {
+                        let _ = self.report(ClientError::DeserializationError {
+                            from,
+                            reason: e.to_string(),
+                        });
+                    }
+                }
+            }
+            WebResponse::ErrorFileNotFound(uuid) if self.media_fetches.contains_key(&uuid) => {
+                self.resolve_requests_for(&uuid.to_string());
+                self.media_library.forget(from, uuid);
+                self.fetch_media_elsewhere(uuid);
+            }
+            WebResponse::ErrorFileNotFound(uuid) => {
+                self.resolve_requests_for(&uuid.to_string());
+                if self.prefetcher.finish(uuid) {
+                    return;
+                }
+                let notification_from = self.id;
+                let _ = self.try_send_for_file(uuid, || WebEvent::FileNotFound {
+                    notification_from,
+                    uuid,
+                });
+                self.file_waiters.remove(&uuid);
+            }
+            WebResponse::BadUuid(uuid) => {
+                self.resolve_requests_for(&uuid.to_string());
+                let _ = self.try_send(WebEvent::BadUuid {
+                    notification_from: self.id,
+                    from,
+                    uuid,
+                });
+            }
+        }
+    }
+}
+
+impl Processor for WebBrowser {
+    fn controller_recv(&self) -> &Receiver<Box<dyn Command>> {
+        &self.controller_recv
+    }
+
+    fn packet_recv(&self) -> &Receiver<Packet> {
+        &self.packet_recv
+    }
+
+    fn assembler(&mut self) -> &mut FragmentAssembler {
+        &mut self.assembler
+    }
+
+    fn routing_handler(&mut self) -> &mut RoutingHandler {
+        &mut self.routing_handler
+    }
+
+    fn handle_command(&mut self, cmd: Box<dyn Command>) -> bool {
+        if self.check_timeouts() {
+            return true;
+        }
+        let (correlation, cmd) = match cmd.into_any().downcast::<CorrelatedCommand>() {
+            Ok(correlated) => (
+                Some(correlated.correlation_id),
+                correlated.command.into_any(),
+            ),
+            Err(cmd) => (None, cmd),
+        };
+        self.correlation = correlation;
+        if let Some(cmd) = cmd.downcast_ref::<WebCommand>() {
+            match cmd {
+                WebCommand::GetCachedFiles => self.handle_get_cached_files(),
+                WebCommand::GetFile(uuid) => self.handle_get_file(*uuid),
+                WebCommand::GetTextFiles => self.handle_get_text_files(),
+                WebCommand::GetTextFile(uuid) => self.handle_get_text_file(*uuid),
+                WebCommand::GetMediaFiles => self.handle_get_media_files(),
+                WebCommand::GetMediaFile { media_id, location } => {
+                    self.handle_get_media_file(*media_id, *location)
+                }
+                _ => self.report(ClientError::ProtocolError(format!(
+                    "unsupported command: {cmd:?}"
+                ))),
+            }
+        } else if let Some(cmd) = cmd.downcast_ref::<NodeCommand>() {
+            match cmd {
+                NodeCommand::AddSender(node_id, sender) => {
+                    self.routing_handler.add_neighbor(*node_id, sender.clone());
+                    return false;
+                }
+                NodeCommand::RemoveSender(node_id) => {
+                    self.routing_handler.remove_neighbor(*node_id);
+                    return false;
+                }
+                NodeCommand::Shutdown => return true,
+            }
+        } else if let Some(cmd) = cmd.downcast_ref::<WebBrowserCommand>() {
+            match cmd {
+                WebBrowserCommand::Tick => false,
+                WebBrowserCommand::GetCacheStats => self.handle_get_cache_stats(),
+                WebBrowserCommand::FlushCache => self.handle_flush_cache(),
+                WebBrowserCommand::SearchTitles(query) => self.handle_search_titles(query),
+                WebBrowserCommand::Visit(page) => self.handle_visit(*page),
+                WebBrowserCommand::Back => {
+                    let page = self.navigation.back();
+                    self.open_page(page)
+                }
+                WebBrowserCommand::Forward => {
+                    let page = self.navigation.forward();
+                    self.open_page(page)
+                }
+                WebBrowserCommand::FollowLink(index) => self.handle_follow_link(*index),
+                WebBrowserCommand::GetNavigation => self.handle_get_navigation(),
+                WebBrowserCommand::AddBookmark(id) => self.handle_add_bookmark(*id),
+                WebBrowserCommand::RemoveBookmark(id) => self.handle_remove_bookmark(*id),
+                WebBrowserCommand::GetBookmarks => self.handle_get_bookmarks(),
+                WebBrowserCommand::GetMediaCatalog(server) => {
+                    self.handle_get_media_catalog(*server)
+                }
+                WebBrowserCommand::Pin(id) => self.handle_pin(*id),
+                WebBrowserCommand::Unpin(id) => self.handle_unpin(*id),
+            }
+        } else {
+            false
+        }
+    }
+
+    // a disconnected controller also stops the command loop, so message handling
+    // carries on regardless of whether its notifications went through
+    fn handle_msg(&mut self, msg: Vec<u8>, from: NodeId, _session_id: u64) {
+        self.correlation = None;
+        let _ = self.try_send(NodeEvent::MessageReceived {
+            notification_from: self.id,
+            from,
+        });
+        match serde_json::from_slice::<WebResponse>(&msg) {
+            Ok(response) => self.handle_response(response, from),
+            Err(e) => {
+                let _ = self.report(ClientError::DeserializationError {
+                    from,
+                    reason: e.to_string(),
+                });
+            }
+        }
+        let _ = self.check_timeouts();
+    }
+}
+
+#[cfg(test)]
 mod web_browser_tests {
     use super::*;
+    use crate::media_fetch::checksum;
     use common::types::{MediaFile, MediaReference, ServerType, TextFile, WebResponse};
     use crossbeam::channel::unbounded;
 
@@ -398,6 +1431,16 @@ mod web_browser_tests {
         WebBrowser::new(1, neighbors, packet_recv, controller_recv, event_send)
     }
 
+    fn create_test_web_browser_with_events() -> (WebBrowser, Receiver<Box<dyn Event>>) {
+        let (_controller_send, controller_recv) = unbounded();
+        let (event_send, event_recv) = unbounded();
+        let (_, packet_recv) = unbounded();
+        let neighbors = HashMap::new();
+
+        let browser = WebBrowser::new(1, neighbors, packet_recv, controller_recv, event_send);
+        (browser, event_recv)
+    }
+
     #[test]
     /// Tests `ServerType` response handling (text server being added to `HashSet`)
     fn test_server_type_identification() {
@@ -409,7 +1452,7 @@ mod web_browser_tests {
         let serialized = serde_json::to_vec(&response).unwrap();
         browser.handle_msg(serialized, 5, 100);
 
-        assert!(browser.text_servers.contains_key(&5));
+        assert!(browser.catalog.contains(5));
     }
 
     #[test]
@@ -452,6 +1495,48 @@ mod web_browser_tests {
         assert_eq!(cached_files.len(), 1);
     }
 
+    #[test]
+    /// Tests that a duplicate `TextFile` response keeps the media already cached
+    /// and delivers nothing again
+    fn test_duplicate_text_file() {
+        let (mut browser, event_recv) = create_test_web_browser_with_events();
+        let media_ref = MediaReference::new(6);
+        let text_file = TextFile::new(
+            "Article".to_string(),
+            "Content".to_string(),
+            vec![media_ref.clone()],
+        );
+        let media_file = MediaFile {
+            id: media_ref.id,
+            title: "Test Image".to_string(),
+            content: vec![vec![1, 2, 3, 4]],
+        };
+        let text_response = serde_json::to_vec(&WebResponse::TextFile {
+            file_data: serde_json::to_vec(&text_file).unwrap(),
+        })
+        .unwrap();
+        browser.handle_msg(text_response.clone(), 5, 102);
+        let media_response = WebResponse::MediaFile {
+            media_data: serde_json::to_vec(&media_file).unwrap(),
+        };
+        browser.handle_msg(serde_json::to_vec(&media_response).unwrap(), 6, 103);
+        assert!(browser.downloads.is_empty());
+        event_recv.try_iter().for_each(drop);
+
+        browser.handle_msg(text_response, 5, 104);
+        assert_eq!(
+            browser.cached_files.media(&text_file),
+            Some(vec![media_file])
+        );
+        assert!(browser.downloads.is_empty());
+        assert!(browser.media_fetches.is_empty());
+        assert!(
+            !event_recv
+                .try_iter()
+                .any(|e| e.as_any().downcast_ref::<WebEvent>().is_some())
+        );
+    }
+
     #[test]
     /// Tests association between `TextFile` and `MediaFile` in `File`
     fn test_media_file_association() {
@@ -480,6 +1565,41 @@ mod web_browser_tests {
         assert_eq!(files.len(), 1);
     }
 
+    #[test]
+    /// Tests that a media no text file references is kept, served again from
+    /// the library and listed in the catalog of its server until a text file
+    /// references it
+    fn test_standalone_media() {
+        let mut browser = create_test_web_browser();
+        let media_ref = MediaReference::new(6);
+        let media = MediaFile {
+            id: media_ref.id,
+            title: "Image".to_string(),
+            content: vec![vec![1]],
+        };
+        browser.handle_get_media_file(media.id, 6);
+        let response = WebResponse::MediaFile {
+            media_data: serde_json::to_vec(&media).unwrap(),
+        };
+        browser.handle_msg(serde_json::to_vec(&response).unwrap(), 6, 140);
+        assert!(browser.requests.is_empty());
+
+        browser.handle_get_media_file(media.id, 6);
+        assert!(browser.requests.is_empty());
+        assert!(browser.media_fetches.is_empty());
+        assert_eq!(browser.media_library.catalog(6), vec![media.id]);
+
+        // a text file referencing it takes it out of the library
+        let text_file = TextFile::new("Page".to_string(), "Content".to_string(), vec![media_ref]);
+        let response = WebResponse::TextFile {
+            file_data: serde_json::to_vec(&text_file).unwrap(),
+        };
+        browser.handle_msg(serde_json::to_vec(&response).unwrap(), 5, 141);
+        assert!(browser.media_fetches.is_empty());
+        assert!(browser.cached_files.has_media(&text_file, media.id));
+        assert_eq!(browser.media_library.media_files().count(), 0);
+    }
+
     #[test]
     /// Tests if different commands do not panick
     fn test_command_responses() {
@@ -502,4 +1622,743 @@ mod web_browser_tests {
             assert!(should_not_continue);
         }
     }
+
+    #[test]
+    /// Tests that a malformed response is reported to the controller
+    fn test_malformed_response_reported() {
+        let (mut browser, event_recv) = create_test_web_browser_with_events();
+
+        browser.handle_msg(b"not a response".to_vec(), 5, 109);
+        let reported = event_recv.try_iter().any(|e| {
+            matches!(
+                e.as_any().downcast_ref::<ClientErrorEvent>(),
+                Some(ClientErrorEvent {
+                    error: ClientError::DeserializationError { from: 5, .. },
+                    ..
+                })
+            )
+        });
+        assert!(reported);
+    }
+
+    #[test]
+    /// Tests that a media its location cannot serve is asked to the other media
+    /// servers known to hold it, and that a corrupted copy is not cached
+    fn test_media_fallback() {
+        let mut browser = create_test_web_browser();
+        let media_ref = MediaReference::new(6);
+        let text_file = TextFile::new(
+            "Article".to_string(),
+            "Content".to_string(),
+            vec![media_ref.clone()],
+        );
+        let media = MediaFile {
+            id: media_ref.id,
+            title: "Image".to_string(),
+            content: vec![vec![1, 2, 3]],
+        };
+        let mut file_data = serde_json::to_value(&text_file).unwrap();
+        file_data["media_refs"][0]["checksum"] = checksum(&media).into();
+        let response = WebResponse::TextFile {
+            file_data: serde_json::to_vec(&file_data).unwrap(),
+        };
+        browser.handle_msg(serde_json::to_vec(&response).unwrap(), 5, 110);
+        for server in [4, 7, 8] {
+            let response = WebResponse::ServerType {
+                server_type: ServerType::MediaServer,
+            };
+            browser.handle_msg(serde_json::to_vec(&response).unwrap(), server, 111);
+        }
+        // referenced there by other text files, unlike server 4
+        browser.media_library.locate(7, media.id);
+        browser.media_library.locate(8, media.id);
+
+        let response = WebResponse::ErrorFileNotFound(media.id);
+        browser.handle_msg(serde_json::to_vec(&response).unwrap(), 6, 112);
+        assert_eq!(browser.media_fetches[&media.id].tried, vec![6, 7]);
+
+        let corrupted = MediaFile {
+            content: vec![vec![0]],
+            ..media.clone()
+        };
+        let response = WebResponse::MediaFile {
+            media_data: serde_json::to_vec(&corrupted).unwrap(),
+        };
+        browser.handle_msg(serde_json::to_vec(&response).unwrap(), 7, 113);
+        assert_eq!(browser.media_fetches[&media.id].tried, vec![6, 7, 8]);
+        assert!(browser.cached_files.media(&text_file).unwrap().is_empty());
+
+        let response = WebResponse::MediaFile {
+            media_data: serde_json::to_vec(&media).unwrap(),
+        };
+        browser.handle_msg(serde_json::to_vec(&response).unwrap(), 8, 114);
+        assert!(browser.media_fetches.is_empty());
+        assert_eq!(browser.cached_files.media(&text_file).unwrap(), vec![media]);
+    }
+
+    #[test]
+    /// Tests that a media download no known server could serve waits for a media
+    /// server to be discovered, then asks it
+    fn test_parked_media_fetch() {
+        let mut browser = create_test_web_browser();
+        let media_ref = MediaReference::new(6);
+        let text_file = TextFile::new(
+            "Article".to_string(),
+            "Content".to_string(),
+            vec![media_ref.clone()],
+        );
+        let response = WebResponse::TextFile {
+            file_data: serde_json::to_vec(&text_file).unwrap(),
+        };
+        browser.handle_msg(serde_json::to_vec(&response).unwrap(), 5, 110);
+        let response = WebResponse::ErrorFileNotFound(media_ref.id);
+        browser.handle_msg(serde_json::to_vec(&response).unwrap(), 6, 111);
+        assert!(browser.media_fetches[&media_ref.id].parked);
+
+        let response = WebResponse::ServerType {
+            server_type: ServerType::MediaServer,
+        };
+        browser.handle_msg(serde_json::to_vec(&response).unwrap(), 9, 112);
+        let fetch = &browser.media_fetches[&media_ref.id];
+        assert!(!fetch.parked);
+        assert_eq!(fetch.tried, vec![6, 9]);
+    }
+
+    #[test]
+    /// Tests that an incomplete file is sent with placeholders past the deadline,
+    /// then again once complete
+    fn test_partial_delivery() {
+        let (browser, event_recv) = create_test_web_browser_with_events();
+        let mut browser = browser.with_partial_delivery(Duration::ZERO);
+        let refs = vec![MediaReference::new(6), MediaReference::new(6)];
+        let text_file = TextFile::new("Article".to_string(), "Content".to_string(), refs.clone());
+        let response = WebResponse::TextFile {
+            file_data: serde_json::to_vec(&text_file).unwrap(),
+        };
+        browser.handle_msg(serde_json::to_vec(&response).unwrap(), 5, 115);
+
+        let media = MediaFile {
+            id: refs[1].id,
+            title: "Image".to_string(),
+            content: vec![vec![1]],
+        };
+        let response = WebResponse::MediaFile {
+            media_data: serde_json::to_vec(&media).unwrap(),
+        };
+        browser.handle_msg(serde_json::to_vec(&response).unwrap(), 6, 116);
+        let files = event_recv
+            .try_iter()
+            .filter_map(|e| match e.into_any().downcast::<WebEvent>() {
+                Ok(event) => match *event {
+                    WebEvent::File { file, .. } => Some(file),
+                    _ => None,
+                },
+                Err(_) => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(files.len(), 1);
+        assert_eq!(
+            files[0].media_files,
+            vec![placeholder(refs[0].id), placeholder(refs[1].id)]
+        );
+
+        let media = MediaFile {
+            id: refs[0].id,
+            ..media
+        };
+        let response = WebResponse::MediaFile {
+            media_data: serde_json::to_vec(&media).unwrap(),
+        };
+        browser.handle_msg(serde_json::to_vec(&response).unwrap(), 6, 117);
+        let completed = event_recv.try_iter().any(|e| {
+            matches!(
+                e.as_any().downcast_ref::<WebBrowserEvent>(),
+                Some(WebBrowserEvent::FileCompleted { missing, .. }) if missing.is_empty()
+            )
+        });
+        assert!(completed);
+        assert!(browser.downloads.is_empty());
+    }
+
+    #[test]
+    /// Tests that file lists are re-queried once due and that refreshed lists
+    /// replace the previous ones
+    fn test_catalog_refresh() {
+        let mut browser = create_test_web_browser().with_catalog_config(CatalogConfig {
+            refresh_every: Duration::ZERO,
+            stale_after: Duration::from_secs(60),
+        });
+        let response = WebResponse::TextFilesList {
+            files: vec![Uuid::from_u128(1).to_string()],
+        };
+        browser.handle_msg(serde_json::to_vec(&response).unwrap(), 5, 118);
+        // the refresh is sent by the timer check that follows every message
+        assert_eq!(browser.requests.len(), 1);
+
+        let response = WebResponse::TextFilesList {
+            files: vec![Uuid::from_u128(2).to_string()],
+        };
+        browser.handle_msg(serde_json::to_vec(&response).unwrap(), 5, 119);
+        assert_eq!(browser.locate_file(Uuid::from_u128(1)), None);
+        assert_eq!(browser.locate_file(Uuid::from_u128(2)), Some(5));
+    }
+
+    #[test]
+    /// Tests visiting cached pages, following a link and going back
+    fn test_navigation() {
+        let mut browser = create_test_web_browser();
+        let target = TextFile::new("Target".to_string(), "End".to_string(), vec![]);
+        let start = TextFile::new(
+            "Start".to_string(),
+            format!("Go to [target]({})", target.id),
+            vec![],
+        );
+        browser.cached_files.insert(start.clone(), vec![]);
+        browser.cached_files.insert(target.clone(), vec![]);
+
+        browser.handle_command(Box::new(WebBrowserCommand::Visit(start.id)));
+        assert_eq!(browser.current_links()[0].target, target.id);
+        browser.handle_command(Box::new(WebBrowserCommand::FollowLink(0)));
+        assert_eq!(browser.navigation.current(), Some(target.id));
+        browser.handle_command(Box::new(WebBrowserCommand::Back));
+        assert_eq!(browser.navigation.current(), Some(start.id));
+        assert_eq!(browser.navigation.forward_history(), vec![target.id]);
+    }
+
+    #[test]
+    /// Tests that a browser restarted on the same cache directory comes back warm
+    fn test_disk_cache_warm_start() {
+        let dir = std::env::temp_dir().join("client-web-browser-warm-start");
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut browser = create_test_web_browser().with_disk_cache(&dir).unwrap();
+
+        let text_file = TextFile::new("Persisted".to_string(), "Content".to_string(), vec![]);
+        let response = WebResponse::TextFile {
+            file_data: serde_json::to_vec(&text_file).unwrap(),
+        };
+        browser.handle_msg(serde_json::to_vec(&response).unwrap(), 5, 108);
+
+        let restarted = create_test_web_browser().with_disk_cache(&dir).unwrap();
+        assert!(restarted.cached_files.contains_key(&text_file));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    /// Tests that a pinned file survives a flush and a restart and is served
+    /// without querying any server
+    fn test_pinned_file_offline() {
+        let dir = std::env::temp_dir().join("client-web-browser-pinned");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let bookmarks = dir.join("bookmarks.json");
+        let mut browser = create_test_web_browser()
+            .with_bookmarks(&bookmarks)
+            .unwrap()
+            .with_disk_cache(dir.join("cache"))
+            .unwrap();
+
+        let text_file = TextFile::new("Pinned".to_string(), "Content".to_string(), vec![]);
+        let response = WebResponse::TextFile {
+            file_data: serde_json::to_vec(&text_file).unwrap(),
+        };
+        browser.handle_msg(serde_json::to_vec(&response).unwrap(), 5, 108);
+        browser.handle_pin(text_file.id);
+        browser.handle_add_bookmark(text_file.id);
+        browser.handle_flush_cache();
+        assert!(browser.cached_files.contains_key(&text_file));
+
+        let mut restarted = create_test_web_browser()
+            .with_bookmarks(&bookmarks)
+            .unwrap()
+            .with_disk_cache(dir.join("cache"))
+            .unwrap();
+        assert_eq!(restarted.bookmarks.list()[0].title, "Pinned");
+        assert!(restarted.cached_files.is_pinned(text_file.id));
+        assert!(restarted.cached_files.contains_key(&text_file));
+        restarted.handle_get_file(text_file.id);
+        assert_eq!(restarted.cached_files.stats().hits, 1);
+        assert!(restarted.requests.is_empty());
+        assert!(restarted.pending_requests.is_empty());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    /// Tests that pinned files evicted while warming the cache come back when
+    /// the bookmarks are set last, the unpinned ones being deleted once running
+    fn test_pins_after_disk_cache() {
+        let dir = std::env::temp_dir().join("client-web-browser-pins-after-cache");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let bookmarks = dir.join("bookmarks.json");
+        let mut browser = create_test_web_browser()
+            .with_disk_cache(dir.join("cache"))
+            .unwrap()
+            .with_bookmarks(&bookmarks)
+            .unwrap();
+        let pinned = TextFile::new("Pinned".to_string(), "Content".to_string(), vec![]);
+        let other = TextFile::new("Other".to_string(), "Content".to_string(), vec![]);
+        for (file, session_id) in [(&pinned, 108), (&other, 109)] {
+            let response = WebResponse::TextFile {
+                file_data: serde_json::to_vec(file).unwrap(),
+            };
+            browser.handle_msg(serde_json::to_vec(&response).unwrap(), 5, session_id);
+        }
+        browser.handle_pin(pinned.id);
+
+        let mut restarted = create_test_web_browser()
+            .with_cache_config(CacheConfig {
+                max_entries: 1,
+                ..CacheConfig::default()
+            })
+            .with_disk_cache(dir.join("cache"))
+            .unwrap()
+            .with_bookmarks(&bookmarks)
+            .unwrap();
+        assert!(restarted.cached_files.contains_key(&pinned));
+        assert!(!restarted.cached_files.contains_key(&other));
+        assert!(
+            dir.join("cache")
+                .join(format!("{}.json", other.id))
+                .exists()
+        );
+
+        restarted.handle_command(Box::new(WebBrowserCommand::Tick));
+        assert!(
+            !dir.join("cache")
+                .join(format!("{}.json", other.id))
+                .exists()
+        );
+        assert!(
+            dir.join("cache")
+                .join(format!("{}.json", pinned.id))
+                .exists()
+        );
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    /// Tests that linked files are prefetched silently and that a request of
+    /// the controller cancels the prefetches in flight
+    fn test_prefetch_links() {
+        let (browser, event_recv) = create_test_web_browser_with_events();
+        let mut browser = browser.with_prefetch(PrefetchConfig::default());
+        let linked = TextFile::new("Linked".to_string(), "Content".to_string(), vec![]);
+        let other = Uuid::from_u128(7);
+        browser.set_files_list(
+            5,
+            vec![format!("{}:Linked", linked.id), format!("{other}:Other")],
+        );
+
+        let page = TextFile::new(
+            "Page".to_string(),
+            format!("See [linked]({})", linked.id),
+            vec![],
+        );
+        let response = WebResponse::TextFile {
+            file_data: serde_json::to_vec(&page).unwrap(),
+        };
+        browser.handle_msg(serde_json::to_vec(&response).unwrap(), 5, 120);
+        assert!(browser.prefetcher.is_background(linked.id));
+        assert_eq!(browser.requests.len(), 1);
+
+        let response = WebResponse::TextFile {
+            file_data: serde_json::to_vec(&linked).unwrap(),
+        };
+        browser.handle_msg(serde_json::to_vec(&response).unwrap(), 5, 121);
+        assert!(browser.cached_files.contains_key(&linked));
+        let delivered = event_recv
+            .try_iter()
+            .filter(|e| {
+                matches!(
+                    e.as_any().downcast_ref::<WebEvent>(),
+                    Some(WebEvent::File { .. })
+                )
+            })
+            .count();
+        assert_eq!(delivered, 1);
+
+        // the controller opening a page cancels what is being prefetched
+        browser.prefetcher.enqueue(other);
+        browser.pump_prefetches();
+        assert!(browser.prefetcher.is_background(other));
+        browser.handle_get_file(Uuid::from_u128(8));
+        assert!(!browser.requests.any(|r, _| matches!(
+            r,
+            WebRequest::FileQuery { file_id } if *file_id == other.to_string()
+        )));
+    }
+
+    #[test]
+    /// Tests that cancelling a prefetch also cancels the media it is fetching
+    /// and drops the incomplete file
+    fn test_prefetch_cancels_media() {
+        let mut browser = create_test_web_browser().with_prefetch(PrefetchConfig::default());
+        let linked = TextFile::new(
+            "Linked".to_string(),
+            "Content".to_string(),
+            vec![MediaReference::new(6)],
+        );
+        browser.set_files_list(5, vec![format!("{}:Linked", linked.id)]);
+        browser.prefetcher.enqueue(linked.id);
+        browser.pump_prefetches();
+        let response = WebResponse::TextFile {
+            file_data: serde_json::to_vec(&linked).unwrap(),
+        };
+        browser.handle_msg(serde_json::to_vec(&response).unwrap(), 5, 140);
+        assert_eq!(browser.media_fetches.len(), 1);
+
+        browser.handle_get_file(Uuid::from_u128(8));
+        assert!(browser.media_fetches.is_empty());
+        assert!(browser.downloads.is_empty());
+        assert!(!browser.cached_files.contains_key(&linked));
+        assert!(
+            !browser
+                .requests
+                .any(|r, _| matches!(r, WebRequest::MediaQuery { .. }))
+        );
+    }
+
+    #[test]
+    /// Tests that the bookmarked files and the ones linked by the most used
+    /// cached pages are prefetched, bookmarks first
+    fn test_prefetch_popular() {
+        let mut browser = create_test_web_browser().with_prefetch(PrefetchConfig {
+            max_in_flight: 1,
+            max_queued: 8,
+        });
+        let (bookmarked, linked, rare) =
+            (Uuid::from_u128(7), Uuid::from_u128(8), Uuid::from_u128(9));
+        browser.set_files_list(
+            5,
+            vec![
+                format!("{bookmarked}:Bookmarked"),
+                format!("{linked}:Linked"),
+                format!("{rare}:Rare"),
+            ],
+        );
+        let page = TextFile::new(
+            "Page".to_string(),
+            format!("See [linked]({linked})"),
+            vec![],
+        );
+        let other = TextFile::new("Other".to_string(), format!("See [rare]({rare})"), vec![]);
+        browser.cached_files.insert(other, vec![]);
+        browser.cached_files.insert(page.clone(), vec![]);
+        browser.get_file(page.id);
+        browser
+            .bookmarks
+            .add(Bookmark {
+                id: bookmarked,
+                title: "Bookmarked".to_string(),
+            })
+            .unwrap();
+
+        browser.handle_command(Box::new(WebBrowserCommand::Tick));
+        assert!(browser.prefetcher.is_background(bookmarked));
+        let response = WebResponse::ErrorFileNotFound(bookmarked);
+        browser.handle_msg(serde_json::to_vec(&response).unwrap(), 5, 140);
+        assert!(browser.prefetcher.is_background(linked));
+        let response = WebResponse::ErrorFileNotFound(linked);
+        browser.handle_msg(serde_json::to_vec(&response).unwrap(), 5, 141);
+        assert!(browser.prefetcher.is_background(rare));
+        let response = WebResponse::ErrorFileNotFound(rare);
+        browser.handle_msg(serde_json::to_vec(&response).unwrap(), 5, 142);
+
+        // files that could not be prefetched are not asked for again
+        browser.handle_command(Box::new(WebBrowserCommand::Tick));
+        assert!(browser.requests.is_empty());
+    }
+
+    #[test]
+    /// Tests that a prefetched file arriving after its prefetch was cancelled is
+    /// dropped, its media left alone
+    fn test_cancelled_prefetch_response() {
+        let mut browser = create_test_web_browser().with_prefetch(PrefetchConfig::default());
+        let linked = TextFile::new(
+            "Linked".to_string(),
+            "Content".to_string(),
+            vec![MediaReference::new(6)],
+        );
+        browser.set_files_list(5, vec![format!("{}:Linked", linked.id)]);
+        browser.prefetcher.enqueue(linked.id);
+        browser.pump_prefetches();
+        browser.handle_get_file(Uuid::from_u128(8));
+        assert!(browser.prefetcher.is_cancelled(linked.id));
+
+        let response = WebResponse::TextFile {
+            file_data: serde_json::to_vec(&linked).unwrap(),
+        };
+        browser.handle_msg(serde_json::to_vec(&response).unwrap(), 5, 140);
+        assert!(!browser.cached_files.contains_key(&linked));
+        assert!(browser.media_fetches.is_empty());
+        assert!(!browser.prefetcher.is_background(linked.id));
+    }
+
+    #[test]
+    /// Tests that identical queries in flight are sent once and that the shared
+    /// media completes every file waiting for it
+    fn test_request_coalescing() {
+        let mut browser = create_test_web_browser();
+        let page = Uuid::from_u128(9);
+        browser.set_files_list(5, vec![format!("{page}:Page")]);
+        browser.handle_get_file(page);
+        browser.handle_get_file(page);
+        assert_eq!(browser.requests.len(), 1);
+
+        let shared = MediaReference::new(6);
+        let first = TextFile::new("First".to_string(), "A".to_string(), vec![shared.clone()]);
+        let second = TextFile::new("Second".to_string(), "B".to_string(), vec![shared.clone()]);
+        for (session, file) in [(130, &first), (131, &second)] {
+            let response = WebResponse::TextFile {
+                file_data: serde_json::to_vec(file).unwrap(),
+            };
+            browser.handle_msg(serde_json::to_vec(&response).unwrap(), 5, session);
+        }
+        let media_queries = |browser: &WebBrowser| {
+            browser
+                .requests
+                .any(|r, _| matches!(r, WebRequest::MediaQuery { .. }))
+        };
+        assert!(media_queries(&browser));
+        assert_eq!(browser.requests.len(), 2);
+
+        let media = MediaFile {
+            id: shared.id,
+            title: "Image".to_string(),
+            content: vec![vec![1]],
+        };
+        let response = WebResponse::MediaFile {
+            media_data: serde_json::to_vec(&media).unwrap(),
+        };
+        browser.handle_msg(serde_json::to_vec(&response).unwrap(), 6, 132);
+        assert!(!media_queries(&browser));
+        for file in [&first, &second] {
+            assert_eq!(browser.cached_files.media(file), Some(vec![media.clone()]));
+        }
+        assert!(browser.downloads.is_empty());
+    }
+
+    #[test]
+    /// Tests that the events caused by a correlated command, through its
+    /// network request, carry its correlation id
+    fn test_correlated_get_file() {
+        let (mut browser, event_recv) = create_test_web_browser_with_events();
+        let text_file = TextFile::new("Article".to_string(), "Content".to_string(), vec![]);
+        browser.set_files_list(5, vec![format!("{}:Article", text_file.id)]);
+
+        browser.handle_command(Box::new(CorrelatedCommand {
+            correlation_id: 7,
+            command: Box::new(WebCommand::GetFile(text_file.id)),
+        }));
+        let response = WebResponse::TextFile {
+            file_data: serde_json::to_vec(&text_file).unwrap(),
+        };
+        browser.handle_msg(serde_json::to_vec(&response).unwrap(), 5, 0);
+
+        let correlated = event_recv
+            .try_iter()
+            .filter_map(|e| e.into_any().downcast::<CorrelatedEvent>().ok())
+            .filter(|e| {
+                matches!(
+                    e.event.as_any().downcast_ref::<WebEvent>(),
+                    Some(WebEvent::File { .. })
+                )
+            })
+            .map(|e| e.correlation_id)
+            .collect::<Vec<_>>();
+        assert_eq!(correlated, vec![7]);
+    }
+
+    #[test]
+    /// Tests that every command joining a query in flight gets its result
+    /// under its own correlation id, whatever session the response comes with
+    fn test_coalesced_correlations() {
+        let (mut browser, event_recv) = create_test_web_browser_with_events();
+        let text_file = TextFile::new("Article".to_string(), "Content".to_string(), vec![]);
+        browser.set_files_list(5, vec![format!("{}:Article", text_file.id)]);
+        for correlation_id in [7, 8] {
+            browser.handle_command(Box::new(CorrelatedCommand {
+                correlation_id,
+                command: Box::new(WebCommand::GetFile(text_file.id)),
+            }));
+        }
+        assert_eq!(browser.requests.len(), 1);
+        let response = WebResponse::TextFile {
+            file_data: serde_json::to_vec(&text_file).unwrap(),
+        };
+        browser.handle_msg(serde_json::to_vec(&response).unwrap(), 5, 99);
+
+        let mut correlated = event_recv
+            .try_iter()
+            .filter_map(|e| e.into_any().downcast::<CorrelatedEvent>().ok())
+            .filter(|e| {
+                matches!(
+                    e.event.as_any().downcast_ref::<WebEvent>(),
+                    Some(WebEvent::File { .. })
+                )
+            })
+            .map(|e| e.correlation_id)
+            .collect::<Vec<_>>();
+        correlated.sort_unstable();
+        assert_eq!(correlated, vec![7, 8]);
+        assert!(browser.file_waiters.is_empty());
+    }
+
+    #[test]
+    /// Tests that every command joining a media download in flight gets the
+    /// media under its own correlation id
+    fn test_coalesced_media_correlations() {
+        let (mut browser, event_recv) = create_test_web_browser_with_events();
+        let media = MediaFile {
+            id: Uuid::from_u128(3),
+            title: "Image".to_string(),
+            content: vec![vec![1, 2]],
+        };
+        for correlation_id in [7, 8] {
+            browser.handle_command(Box::new(CorrelatedCommand {
+                correlation_id,
+                command: Box::new(WebCommand::GetMediaFile {
+                    media_id: media.id,
+                    location: 6,
+                }),
+            }));
+        }
+        assert_eq!(browser.requests.len(), 1);
+        let response = WebResponse::MediaFile {
+            media_data: serde_json::to_vec(&media).unwrap(),
+        };
+        browser.handle_msg(serde_json::to_vec(&response).unwrap(), 6, 99);
+
+        let mut correlated = event_recv
+            .try_iter()
+            .filter_map(|e| e.into_any().downcast::<CorrelatedEvent>().ok())
+            .filter(|e| {
+                matches!(
+                    e.event.as_any().downcast_ref::<WebEvent>(),
+                    Some(WebEvent::MediaFile { file, .. }) if file.id == media.id
+                )
+            })
+            .map(|e| e.correlation_id)
+            .collect::<Vec<_>>();
+        correlated.sort_unstable();
+        assert_eq!(correlated, vec![7, 8]);
+        assert!(browser.media_waiters.is_empty());
+    }
+
+    #[test]
+    /// Tests that queries are tracked until the matching response arrives
+    fn test_request_tracking() {
+        let mut browser = create_test_web_browser();
+        let text_file = TextFile::new("Tracked".to_string(), "Content".to_string(), vec![]);
+        browser
+            .catalog
+            .update(5, vec![text_file.id.to_string()], Instant::now());
+
+        browser.handle_command(Box::new(WebCommand::GetFile(text_file.id)));
+        assert_eq!(browser.requests.len(), 1);
+
+        let response = WebResponse::TextFile {
+            file_data: serde_json::to_vec(&text_file).unwrap(),
+        };
+        browser.handle_msg(serde_json::to_vec(&response).unwrap(), 5, 107);
+        assert!(browser.requests.is_empty());
+    }
+
+    #[test]
+    /// Tests that a request waiting for a location fails under its command's
+    /// correlation id once it waited as long as a tracked request would
+    fn test_pending_request_deadline() {
+        let (browser, event_recv) = create_test_web_browser_with_events();
+        let mut browser = browser.with_retry_policy(RetryPolicy {
+            timeout: Duration::ZERO,
+            backoff: 1,
+            max_retries: 0,
+            reroute_after: Duration::from_secs(60),
+        });
+        browser.handle_command(Box::new(CorrelatedCommand {
+            correlation_id: 7,
+            command: Box::new(WebCommand::GetFile(Uuid::from_u128(9))),
+        }));
+        assert_eq!(browser.pending_requests.len(), 1);
+
+        browser.handle_command(Box::new(WebBrowserCommand::Tick));
+        assert!(browser.pending_requests.is_empty());
+        let failed = event_recv
+            .try_iter()
+            .filter_map(|e| e.into_any().downcast::<CorrelatedEvent>().ok())
+            .any(|e| {
+                e.correlation_id == 7
+                    && matches!(
+                        e.event.as_any().downcast_ref::<ClientErrorEvent>(),
+                        Some(ClientErrorEvent {
+                            error: ClientError::NoLocationError,
+                            ..
+                        })
+                    )
+            });
+        assert!(failed);
+    }
+
+    #[test]
+    /// Tests that a text server never answering its list query stops holding
+    /// up the requests waiting for a location
+    fn test_unanswered_list_query() {
+        let (browser, event_recv) = create_test_web_browser_with_events();
+        let mut browser = browser.with_retry_policy(RetryPolicy {
+            timeout: Duration::ZERO,
+            backoff: 1,
+            max_retries: 0,
+            reroute_after: Duration::from_secs(60),
+        });
+        let missing = Uuid::from_u128(9);
+        browser.handle_get_file(missing);
+        for (_, _, deadline) in browser.pending_requests.values_mut() {
+            *deadline = Instant::now() + Duration::from_secs(60);
+        }
+
+        // the list query goes unanswered past its deadline straight away
+        let response = WebResponse::ServerType {
+            server_type: ServerType::TextServer,
+        };
+        browser.handle_msg(serde_json::to_vec(&response).unwrap(), 5, 150);
+        assert!(browser.pending_requests.is_empty());
+        assert!(!browser.catalog.contains(5));
+        let not_found = event_recv.try_iter().any(|e| {
+            matches!(
+                e.as_any().downcast_ref::<WebEvent>(),
+                Some(WebEvent::FileNotFound { uuid, .. }) if *uuid == missing
+            )
+        });
+        assert!(not_found);
+    }
+
+    #[test]
+    /// Tests that concurrent `GetFile` commands for unknown files are all kept pending
+    fn test_multiple_pending_requests() {
+        let mut browser = create_test_web_browser();
+        let first = Uuid::from_u128(1);
+        let second = Uuid::from_u128(2);
+
+        browser.handle_command(Box::new(WebCommand::GetFile(first)));
+        browser.handle_command(Box::new(WebCommand::GetFile(second)));
+        assert_eq!(browser.pending_requests.len(), 2);
+
+        // one text server known but not listed yet: requests survive its discovery
+        let response = WebResponse::ServerType {
+            server_type: ServerType::TextServer,
+        };
+        browser.handle_msg(serde_json::to_vec(&response).unwrap(), 5, 104);
+        browser.catalog.discover(6);
+        let response = WebResponse::TextFilesList {
+            files: vec![first.to_string()],
+        };
+        browser.handle_msg(serde_json::to_vec(&response).unwrap(), 5, 105);
+        assert_eq!(browser.pending_requests.len(), 1);
+        assert!(browser.pending_requests.contains_key(&second));
+
+        // every known text server answered without the file: request fails
+        let response = WebResponse::TextFilesList { files: vec![] };
+        browser.handle_msg(serde_json::to_vec(&response).unwrap(), 6, 106);
+        assert!(browser.pending_requests.is_empty());
+    }
 }